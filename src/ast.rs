@@ -0,0 +1,159 @@
+use crate::{Alteration, Check, Expr, OnDelete};
+
+/// An SQL statement as written, before the tables and columns it names are
+/// looked up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    Select(Select),
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    CreateTable(CreateTable),
+    CreateIndex(CreateIndex),
+    AlterTable {
+        table: String,
+        alteration: Alteration,
+    },
+    DropTable(String),
+    DropIndex(String),
+}
+
+/// `select` alone, which reads the tutorial's users table, or
+/// `select <column>, ... [from <source>] [where <filter>]
+/// [order by <expression> [asc|desc]]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Select {
+    /// Empty for `select` alone.
+    pub columns: Vec<ResultColumn>,
+    pub from: Option<TableRef>,
+    pub filter: Option<Expr>,
+    pub order_by: Option<OrderBy>,
+}
+
+/// A column of the rows a `select` returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResultColumn {
+    /// `*`: every column of the source.
+    All,
+    /// `<table>.*`.
+    AllOf(String),
+    /// `<expression> [[as] <alias>]`.
+    Expr {
+        expr: Expr,
+        alias: Option<String>,
+        /// The expression as written.
+        sql: String,
+    },
+}
+
+/// What a `select` reads its rows from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableRef {
+    Table(String),
+    /// A table-valued function such as `json_each(<json>)`.
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderBy {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+/// `insert [into <table>] [(<column>, ...)] <values>`, on the users table
+/// if none is named.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Insert {
+    pub table: Option<String>,
+    pub columns: Option<Vec<String>>,
+    pub values: Values,
+}
+
+/// The rows an insert adds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Values {
+    /// `<value> ...`: one row of values separated by whitespace, as
+    /// written: numbers, `null`, quoted strings and blobs, or unquoted text.
+    Words(Vec<String>),
+    /// `values (<expression>, ...), ...`.
+    Rows(Vec<Vec<Expr>>),
+}
+
+/// `update <table> set <column> = <value>, ... [where <filter>]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Update {
+    pub table: String,
+    pub assignments: Vec<(String, Expr)>,
+    pub filter: Option<Expr>,
+}
+
+/// `delete from <table> [where <filter>]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delete {
+    pub table: String,
+    pub filter: Option<Expr>,
+}
+
+/// `create table <name> (<column definition>, ..., <table constraint>, ...)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateTable {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+}
+
+/// `<name> [<type>] [<constraint> ...]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDef {
+    pub name: String,
+    /// The declared type as written, empty if there is none.
+    pub declared_type: String,
+    pub constraints: Vec<ColumnConstraint>,
+    /// The whole definition as written.
+    pub sql: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnConstraint {
+    NotNull,
+    Null,
+    PrimaryKey { autoincrement: bool },
+    Unique,
+    Default(Expr),
+    Collate(String),
+    Check(Check),
+    References(References),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableConstraint {
+    Unique(Vec<String>),
+    Check(Check),
+    ForeignKey {
+        column: String,
+        references: References,
+    },
+}
+
+/// `references <table> [(<column>)] [on delete <action>] [[not] deferrable
+/// [initially deferred | initially immediate]]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct References {
+    pub table: String,
+    /// The referenced column, the table's primary key if unset.
+    pub column: Option<String>,
+    pub on_delete: OnDelete,
+    pub deferred: bool,
+}
+
+/// `create [unique] index <name> on <table> (<column>, ...)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateIndex {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
@@ -0,0 +1,635 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use crate::row::{decode_record, encode_record};
+use crate::{
+    INTERNAL_CELL_CHILD_SIZE, LEAF_CELL_HEADER_SIZE, LEAF_CELL_LENGTH_SIZE, MAX_PAYLOAD_SIZE,
+    NODE_HEADER_SIZE, NODE_TYPE_OFFSET, NUM_CELLS_OFFSET, PAGE_SIZE, Pager, RIGHT_POINTER_OFFSET,
+    Value,
+};
+
+/// Largest leaf cell, key included. Keeping cells under a third of a node
+/// guarantees that splitting an overflowing node yields two nodes that fit.
+const MAX_CELL_SIZE: usize = LEAF_CELL_HEADER_SIZE + MAX_PAYLOAD_SIZE;
+
+/// The key of a B-tree cell. Table trees are keyed by rowid; index trees by
+/// the indexed values followed by the rowid of the row they point to. Each
+/// kind of tree has its own node types so a page tells what it holds.
+pub(crate) trait Key: Clone + Ord + Debug {
+    const NODE_TYPE_INTERNAL: u8;
+    const NODE_TYPE_LEAF: u8;
+
+    /// Number of bytes the key takes in a cell.
+    fn size(&self) -> usize;
+    fn read(page: &[u8], offset: usize) -> Self;
+    fn write(&self, page: &mut [u8], offset: usize);
+}
+
+impl Key for i64 {
+    const NODE_TYPE_INTERNAL: u8 = 1;
+    const NODE_TYPE_LEAF: u8 = 2;
+
+    fn size(&self) -> usize {
+        size_of::<i64>()
+    }
+
+    fn read(page: &[u8], offset: usize) -> Self {
+        read_i64(page, offset)
+    }
+
+    fn write(&self, page: &mut [u8], offset: usize) {
+        write_i64(page, offset, *self);
+    }
+}
+
+/// An index entry: the values of the indexed columns and the rowid of the
+/// row holding them, stored as a record. Entries are ordered by value, then
+/// by rowid, so entries with equal values are all distinct keys.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct IndexKey {
+    pub values: Vec<Value>,
+    pub rowid: i64,
+}
+
+impl IndexKey {
+    fn record(&self) -> Vec<u8> {
+        let mut values = self.values.clone();
+        values.push(Value::Integer(self.rowid));
+        encode_record(&values)
+    }
+
+    /// Compares only the indexed values, ignoring the rowid.
+    pub fn cmp_values(&self, other: &IndexKey) -> Ordering {
+        self.values.cmp(&other.values)
+    }
+}
+
+impl Key for IndexKey {
+    const NODE_TYPE_INTERNAL: u8 = 3;
+    const NODE_TYPE_LEAF: u8 = 4;
+
+    fn size(&self) -> usize {
+        self.record().len()
+    }
+
+    fn read(page: &[u8], offset: usize) -> Self {
+        let (mut values, _) = decode_record(&page[offset..]);
+        match values.pop() {
+            Some(Value::Integer(rowid)) => IndexKey { values, rowid },
+            _ => panic!("Corrupt index: entry without a rowid"),
+        }
+    }
+
+    fn write(&self, page: &mut [u8], offset: usize) {
+        let record = self.record();
+        page[offset..offset + record.len()].copy_from_slice(&record);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum BTreeError {
+    /// A cell with the same key is already stored in the tree.
+    DuplicateKey,
+    /// The pager has no page left for the nodes a split needs.
+    Full,
+    /// The payload does not fit in a leaf cell.
+    PayloadTooLarge,
+}
+
+/// A leaf cell: a key and the serialized row stored under it. Index
+/// entries keep everything in the key and have an empty payload.
+#[derive(Debug, Clone)]
+pub(crate) struct LeafCell<K> {
+    pub key: K,
+    pub payload: Vec<u8>,
+}
+
+impl<K: Key> LeafCell<K> {
+    fn size(&self) -> usize {
+        self.key.size() + LEAF_CELL_LENGTH_SIZE + self.payload.len()
+    }
+}
+
+/// An internal cell: a child page and the largest key stored under it.
+#[derive(Debug, Clone)]
+pub(crate) struct InternalCell<K> {
+    pub child: usize,
+    pub key: K,
+}
+
+impl<K: Key> InternalCell<K> {
+    fn size(&self) -> usize {
+        INTERNAL_CELL_CHILD_SIZE + self.key.size()
+    }
+}
+
+/// A leaf node holds the cells of the tree ordered by key. Leaves are
+/// chained left to right so that a scan never goes back up the tree.
+#[derive(Debug)]
+pub(crate) struct LeafNode<K> {
+    pub cells: Vec<LeafCell<K>>,
+    /// Page of the next leaf, 0 for the last one. Page 0 is the file
+    /// header, so it can never be a leaf.
+    pub next_leaf: usize,
+}
+
+/// An internal node routes a key to the first child whose largest key is
+/// not smaller than it, or to the right child when there is none.
+#[derive(Debug)]
+pub(crate) struct InternalNode<K> {
+    pub cells: Vec<InternalCell<K>>,
+    pub right_child: usize,
+}
+
+#[derive(Debug)]
+pub(crate) enum Node<K> {
+    Internal(InternalNode<K>),
+    Leaf(LeafNode<K>),
+}
+
+impl<K> Default for LeafNode<K> {
+    fn default() -> Self {
+        LeafNode {
+            cells: Vec::new(),
+            next_leaf: 0,
+        }
+    }
+}
+
+impl<K: Key> LeafNode<K> {
+    fn size(&self) -> usize {
+        NODE_HEADER_SIZE + self.cells.iter().map(LeafCell::size).sum::<usize>()
+    }
+}
+
+impl<K: Key> InternalNode<K> {
+    fn size(&self) -> usize {
+        NODE_HEADER_SIZE + self.cells.iter().map(InternalCell::size).sum::<usize>()
+    }
+
+    /// Index of the child a key is routed to; `cells.len()` is the right child.
+    fn child_index(&self, key: &K) -> usize {
+        self.cells.partition_point(|cell| cell.key < *key)
+    }
+
+    fn child(&self, index: usize) -> usize {
+        self.cells
+            .get(index)
+            .map_or(self.right_child, |cell| cell.child)
+    }
+
+    /// Records that the child at `index` was split into `left`, keeping keys
+    /// up to `separator`, and a new `right` node.
+    fn insert_split(&mut self, index: usize, left: usize, separator: K, right: usize) {
+        match self.cells.get_mut(index) {
+            Some(cell) => {
+                let cell = std::mem::replace(
+                    cell,
+                    InternalCell {
+                        child: left,
+                        key: separator,
+                    },
+                );
+                self.cells.insert(
+                    index + 1,
+                    InternalCell {
+                        child: right,
+                        key: cell.key,
+                    },
+                );
+            }
+            None => {
+                self.cells.push(InternalCell {
+                    child: left,
+                    key: separator,
+                });
+                self.right_child = right;
+            }
+        }
+    }
+}
+
+impl<K: Key> Node<K> {
+    /// Decodes the node stored in a page.
+    pub fn read(page: &[u8; PAGE_SIZE]) -> Node<K> {
+        let num_cells = read_u16(page, NUM_CELLS_OFFSET) as usize;
+        let right_pointer = read_u32(page, RIGHT_POINTER_OFFSET) as usize;
+        let mut offset = NODE_HEADER_SIZE;
+
+        match page[NODE_TYPE_OFFSET] {
+            node_type if node_type == K::NODE_TYPE_INTERNAL => {
+                let cells = (0..num_cells)
+                    .map(|_| {
+                        let cell = InternalCell {
+                            child: read_u32(page, offset) as usize,
+                            key: K::read(page, offset + INTERNAL_CELL_CHILD_SIZE),
+                        };
+                        offset += cell.size();
+                        cell
+                    })
+                    .collect();
+                Node::Internal(InternalNode {
+                    cells,
+                    right_child: right_pointer,
+                })
+            }
+            node_type if node_type == K::NODE_TYPE_LEAF => {
+                let cells = (0..num_cells)
+                    .map(|_| {
+                        let key = K::read(page, offset);
+                        let length = read_u16(page, offset + key.size()) as usize;
+                        let start = offset + key.size() + LEAF_CELL_LENGTH_SIZE;
+                        offset = start + length;
+                        LeafCell {
+                            key,
+                            payload: page[start..offset].to_vec(),
+                        }
+                    })
+                    .collect();
+                Node::Leaf(LeafNode {
+                    cells,
+                    next_leaf: right_pointer,
+                })
+            }
+            node_type => panic!("Corrupt page: unknown node type {}", node_type),
+        }
+    }
+
+    /// Encodes the node into a page, overwriting its previous content.
+    pub fn write(&self, page: &mut [u8; PAGE_SIZE]) {
+        page.fill(0);
+        let mut offset = NODE_HEADER_SIZE;
+
+        let (node_type, num_cells, right_pointer) = match self {
+            Node::Internal(node) => {
+                for cell in &node.cells {
+                    write_u32(page, offset, cell.child as u32);
+                    cell.key.write(page, offset + INTERNAL_CELL_CHILD_SIZE);
+                    offset += cell.size();
+                }
+                (K::NODE_TYPE_INTERNAL, node.cells.len(), node.right_child)
+            }
+            Node::Leaf(node) => {
+                for cell in &node.cells {
+                    cell.key.write(page, offset);
+                    let key_size = cell.key.size();
+                    write_u16(page, offset + key_size, cell.payload.len() as u16);
+                    let start = offset + key_size + LEAF_CELL_LENGTH_SIZE;
+                    offset = start + cell.payload.len();
+                    page[start..offset].copy_from_slice(&cell.payload);
+                }
+                (K::NODE_TYPE_LEAF, node.cells.len(), node.next_leaf)
+            }
+        };
+
+        page[NODE_TYPE_OFFSET] = node_type;
+        write_u16(page, NUM_CELLS_OFFSET, num_cells as u16);
+        write_u32(page, RIGHT_POINTER_OFFSET, right_pointer as u32);
+    }
+}
+
+/// A B+tree with every cell stored in the leaves. Table trees are keyed by
+/// rowid, which is the default.
+/// The root never moves: when it splits, its content is moved to two new
+/// children and the root page becomes their parent.
+#[derive(Debug)]
+pub(crate) struct BTree<K = i64> {
+    pub root_page: usize,
+    key: PhantomData<K>,
+}
+
+impl<K: Key> BTree<K> {
+    pub fn new(root_page: usize) -> BTree<K> {
+        BTree {
+            root_page,
+            key: PhantomData,
+        }
+    }
+
+    /// Allocates the root page of a new, empty tree.
+    pub fn create(pager: &mut Pager) -> Result<BTree<K>, BTreeError> {
+        let root_page = pager.allocate_page().ok_or(BTreeError::Full)?;
+        write_node(pager, root_page, &Node::Leaf(LeafNode::<K>::default()));
+        Ok(BTree::new(root_page))
+    }
+
+    /// Descends to the leaf that holds `key`, or would hold it. Returns the
+    /// internal nodes visited, with the index of the child taken in each.
+    fn find_leaf(&self, pager: &mut Pager, key: &K) -> (Vec<(usize, usize)>, usize, LeafNode<K>) {
+        let mut path = Vec::new();
+        let mut page_num = self.root_page;
+        loop {
+            match read_node(pager, page_num) {
+                Node::Internal(node) => {
+                    let index = node.child_index(key);
+                    path.push((page_num, index));
+                    page_num = node.child(index);
+                }
+                Node::Leaf(leaf) => return (path, page_num, leaf),
+            }
+        }
+    }
+
+    /// Returns the payload stored under `key`, if any.
+    pub fn get(&self, pager: &mut Pager, key: &K) -> Option<Vec<u8>> {
+        let (_, _, mut leaf) = self.find_leaf(pager, key);
+        let position = leaf.cells.binary_search_by(|cell| cell.key.cmp(key)).ok()?;
+        Some(leaf.cells.swap_remove(position).payload)
+    }
+
+    /// Returns the smallest key that is not smaller than `key`.
+    pub fn seek(&self, pager: &mut Pager, key: &K) -> Option<K> {
+        let (_, _, mut leaf) = self.find_leaf(pager, key);
+        loop {
+            let position = leaf.cells.partition_point(|cell| cell.key < *key);
+            if let Some(cell) = leaf.cells.get(position) {
+                return Some(cell.key.clone());
+            }
+            if leaf.next_leaf == 0 {
+                return None;
+            }
+            leaf = match read_node(pager, leaf.next_leaf) {
+                Node::Leaf(leaf) => leaf,
+                Node::Internal(_) => panic!("Corrupt tree: leaves only link to leaves"),
+            };
+        }
+    }
+
+    /// Returns the keys from the smallest one that is not smaller than
+    /// `key`, in order, for as long as `matches` accepts them.
+    pub fn keys_from(
+        &self,
+        pager: &mut Pager,
+        key: &K,
+        mut matches: impl FnMut(&K) -> bool,
+    ) -> Vec<K> {
+        let (_, _, mut leaf) = self.find_leaf(pager, key);
+        let mut position = leaf.cells.partition_point(|cell| cell.key < *key);
+        let mut keys = Vec::new();
+        loop {
+            for cell in &leaf.cells[position..] {
+                if !matches(&cell.key) {
+                    return keys;
+                }
+                keys.push(cell.key.clone());
+            }
+            if leaf.next_leaf == 0 {
+                return keys;
+            }
+            leaf = match read_node(pager, leaf.next_leaf) {
+                Node::Leaf(leaf) => leaf,
+                Node::Internal(_) => panic!("Corrupt tree: leaves only link to leaves"),
+            };
+            position = 0;
+        }
+    }
+
+    /// Returns the page of the leaf holding the smallest keys.
+    pub fn first_leaf(&self, pager: &mut Pager) -> usize {
+        let mut page_num = self.root_page;
+        while let Node::Internal(node) = read_node::<K>(pager, page_num) {
+            page_num = node.child(0);
+        }
+        page_num
+    }
+
+    /// Returns the largest key stored in the tree.
+    pub fn last_key(&self, pager: &mut Pager) -> Option<K> {
+        let mut page_num = self.root_page;
+        loop {
+            match read_node::<K>(pager, page_num) {
+                Node::Internal(node) => page_num = node.right_child,
+                Node::Leaf(mut leaf) => return leaf.cells.pop().map(|cell| cell.key),
+            }
+        }
+    }
+
+    /// Returns every page of the tree, the root included, to the free list.
+    /// The tree must not be used afterwards.
+    pub fn destroy(&self, pager: &mut Pager) {
+        let mut pages = vec![self.root_page];
+        while let Some(page_num) = pages.pop() {
+            if let Node::Internal(node) = read_node::<K>(pager, page_num) {
+                pages.extend(node.cells.iter().map(|cell| cell.child));
+                pages.push(node.right_child);
+            }
+            pager.free_page(page_num);
+        }
+    }
+
+    /// Removes the cell stored under `key`, returning whether there was one.
+    /// Nodes are never merged: a leaf can be left empty and the separators
+    /// above it keep bounding the keys below them.
+    pub fn delete(&self, pager: &mut Pager, key: &K) -> bool {
+        let (_, leaf_page, mut leaf) = self.find_leaf(pager, key);
+        match leaf.cells.binary_search_by(|cell| cell.key.cmp(key)) {
+            Ok(position) => {
+                leaf.cells.remove(position);
+                write_node(pager, leaf_page, &Node::Leaf(leaf));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Inserts a new cell, splitting the nodes that overflow on the way up.
+    pub fn insert(&self, pager: &mut Pager, key: K, payload: Vec<u8>) -> Result<(), BTreeError> {
+        let cell = LeafCell { key, payload };
+        if cell.size() > MAX_CELL_SIZE {
+            return Err(BTreeError::PayloadTooLarge);
+        }
+
+        let (path, leaf_page, mut leaf) = self.find_leaf(pager, &cell.key);
+        let position = match leaf.cells.binary_search_by(|c| c.key.cmp(&cell.key)) {
+            Ok(_) => return Err(BTreeError::DuplicateKey),
+            Err(position) => position,
+        };
+        leaf.cells.insert(position, cell);
+        if leaf.size() <= PAGE_SIZE {
+            write_node(pager, leaf_page, &Node::Leaf(leaf));
+            return Ok(());
+        }
+
+        if self.pages_needed_for_split(pager, &path) > pager.free_page_count() {
+            return Err(BTreeError::Full);
+        }
+
+        // Appending past the last cell is the common case of increasing keys:
+        // keep the left node full and start the new one with the new cell only.
+        let split_at = if position == leaf.cells.len() - 1 {
+            position
+        } else {
+            balanced_split(&leaf.cells.iter().map(LeafCell::size).collect::<Vec<_>>())
+        };
+        let right_cells = leaf.cells.split_off(split_at);
+        let separator = leaf
+            .cells
+            .last()
+            .expect("Left node is never empty")
+            .key
+            .clone();
+
+        if leaf_page == self.root_page {
+            let right = LeafNode {
+                cells: right_cells,
+                next_leaf: 0,
+            };
+            self.split_root(pager, Node::Leaf(leaf), separator, Node::Leaf(right));
+            return Ok(());
+        }
+
+        let right = LeafNode {
+            cells: right_cells,
+            next_leaf: leaf.next_leaf,
+        };
+        let right_page = allocate(pager, &Node::Leaf(right));
+        leaf.next_leaf = right_page;
+        write_node(pager, leaf_page, &Node::Leaf(leaf));
+
+        let mut split = (leaf_page, separator, right_page);
+        for &(page_num, index) in path.iter().rev() {
+            let Node::Internal(mut node) = read_node::<K>(pager, page_num) else {
+                unreachable!("Path only holds internal nodes");
+            };
+            let (left, separator, right) = split;
+            node.insert_split(index, left, separator, right);
+            if node.size() <= PAGE_SIZE {
+                write_node(pager, page_num, &Node::Internal(node));
+                return Ok(());
+            }
+
+            let split_at = balanced_split(
+                &node
+                    .cells
+                    .iter()
+                    .map(InternalCell::size)
+                    .collect::<Vec<_>>(),
+            );
+            let mut right_cells = node.cells.split_off(split_at);
+            let middle = right_cells.remove(0);
+            let right = InternalNode {
+                cells: right_cells,
+                right_child: node.right_child,
+            };
+            node.right_child = middle.child;
+
+            if page_num == self.root_page {
+                self.split_root(
+                    pager,
+                    Node::Internal(node),
+                    middle.key,
+                    Node::Internal(right),
+                );
+                return Ok(());
+            }
+            let right_page = allocate(pager, &Node::Internal(right));
+            write_node(pager, page_num, &Node::Internal(node));
+            split = (page_num, middle.key, right_page);
+        }
+
+        unreachable!("The root is always the first node of the path");
+    }
+
+    /// Counts the pages an insert overflowing the leaf at the end of `path`
+    /// allocates: one per split node, two for the root. Internal cells are
+    /// never larger than a third of a node, so any of them may overflow it.
+    fn pages_needed_for_split(&self, pager: &mut Pager, path: &[(usize, usize)]) -> usize {
+        let mut needed = if path.is_empty() { 2 } else { 1 };
+        for (depth, &(page_num, _)) in path.iter().enumerate().rev() {
+            let Node::Internal(node) = read_node::<K>(pager, page_num) else {
+                unreachable!("Path only holds internal nodes");
+            };
+            if node.size() + INTERNAL_CELL_CHILD_SIZE + MAX_CELL_SIZE <= PAGE_SIZE {
+                break;
+            }
+            needed += if depth == 0 { 2 } else { 1 };
+        }
+        needed
+    }
+
+    /// Moves the two halves of a split root to new pages and turns the root
+    /// into an internal node pointing at them.
+    fn split_root(&self, pager: &mut Pager, mut left: Node<K>, separator: K, right: Node<K>) {
+        let left_page = reserve(pager);
+        let right_page = reserve(pager);
+        if let Node::Leaf(leaf) = &mut left {
+            leaf.next_leaf = right_page;
+        }
+        write_node(pager, left_page, &left);
+        write_node(pager, right_page, &right);
+
+        let root = InternalNode {
+            cells: vec![InternalCell {
+                child: left_page,
+                key: separator,
+            }],
+            right_child: right_page,
+        };
+        write_node(pager, self.root_page, &Node::Internal(root));
+    }
+}
+
+/// Picks the split point that leaves about half of the bytes in each node.
+fn balanced_split(sizes: &[usize]) -> usize {
+    let total: usize = sizes.iter().sum();
+    let mut left = 0;
+    for (index, size) in sizes.iter().enumerate() {
+        if left + size > total / 2 {
+            return index.max(1);
+        }
+        left += size;
+    }
+    sizes.len() - 1
+}
+
+pub(crate) fn read_node<K: Key>(pager: &mut Pager, page_num: usize) -> Node<K> {
+    Node::read(pager.get_page(page_num))
+}
+
+fn write_node<K: Key>(pager: &mut Pager, page_num: usize, node: &Node<K>) {
+    node.write(pager.get_page(page_num));
+}
+
+/// Allocates a page for a split. Callers check beforehand that enough
+/// pages are left.
+fn reserve(pager: &mut Pager) -> usize {
+    pager
+        .allocate_page()
+        .expect("Pages for a split are checked up front")
+}
+
+/// Stores a node in a newly allocated page.
+fn allocate<K: Key>(pager: &mut Pager, node: &Node<K>) -> usize {
+    let page_num = reserve(pager);
+    write_node(pager, page_num, node);
+    page_num
+}
+
+fn read_u16(page: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([page[offset], page[offset + 1]])
+}
+
+fn read_u32(page: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; size_of::<u32>()];
+    bytes.copy_from_slice(&page[offset..offset + size_of::<u32>()]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_i64(page: &[u8], offset: usize) -> i64 {
+    let mut bytes = [0u8; size_of::<i64>()];
+    bytes.copy_from_slice(&page[offset..offset + size_of::<i64>()]);
+    i64::from_le_bytes(bytes)
+}
+
+fn write_u16(page: &mut [u8], offset: usize, value: u16) {
+    page[offset..offset + size_of::<u16>()].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(page: &mut [u8], offset: usize, value: u32) {
+    page[offset..offset + size_of::<u32>()].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_i64(page: &mut [u8], offset: usize, value: i64) {
+    page[offset..offset + size_of::<i64>()].copy_from_slice(&value.to_le_bytes());
+}
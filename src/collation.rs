@@ -0,0 +1,113 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use crate::Value;
+
+/// Maps a text to the key it sorts by.
+type SortKey = dyn Fn(&str) -> String + Send + Sync;
+
+/// Collations registered with `Collation::register`, on top of the
+/// built-in ones.
+static REGISTERED: RwLock<Vec<Collation>> = RwLock::new(Vec::new());
+
+/// A way of comparing text: two texts compare as the keys the collation
+/// maps them to, so `NOCASE` maps both `Alice` and `alice` to `alice`.
+/// Indexes store these keys, which keeps their order consistent with the
+/// collation. Values other than text compare the same under every
+/// collation.
+#[derive(Clone)]
+pub struct Collation {
+    name: String,
+    key: Option<Arc<SortKey>>,
+}
+
+impl Collation {
+    /// Compares the bytes of the text, the default.
+    pub const BINARY: &str = "binary";
+    /// Ignores the case of ASCII letters.
+    pub const NOCASE: &str = "nocase";
+    /// Ignores trailing spaces.
+    pub const RTRIM: &str = "rtrim";
+
+    /// Creates a collation comparing texts by the keys `key` maps them to.
+    pub fn new(name: &str, key: impl Fn(&str) -> String + Send + Sync + 'static) -> Collation {
+        Collation {
+            name: name.to_ascii_lowercase(),
+            key: Some(Arc::new(key)),
+        }
+    }
+
+    /// The `BINARY` collation.
+    pub fn binary() -> Collation {
+        Collation {
+            name: Self::BINARY.to_string(),
+            key: None,
+        }
+    }
+
+    /// Makes a collation available to every database opened by the
+    /// process, replacing any registered collation of the same name. The
+    /// built-in collations cannot be replaced.
+    pub fn register(collation: Collation) {
+        let mut registered = REGISTERED.write().expect("Collation registry is poisoned");
+        registered.retain(|other| other.name != collation.name);
+        registered.push(collation);
+    }
+
+    /// Returns the collation with the given name, ignoring case, if it is
+    /// built in or registered.
+    pub fn lookup(name: &str) -> Option<Collation> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            Self::BINARY => Some(Collation::binary()),
+            Self::NOCASE => Some(Collation::new(Self::NOCASE, |text| {
+                text.to_ascii_lowercase()
+            })),
+            Self::RTRIM => Some(Collation::new(Self::RTRIM, |text| {
+                text.trim_end_matches(' ').to_string()
+            })),
+            _ => REGISTERED
+                .read()
+                .expect("Collation registry is poisoned")
+                .iter()
+                .find(|collation| collation.name == name)
+                .cloned(),
+        }
+    }
+
+    /// Returns the collation with the given name, or `BINARY` if there is
+    /// none, as for a database whose collation is not registered yet.
+    /// Statements using an unknown collation are refused when prepared.
+    pub(crate) fn named(name: Option<&str>) -> Collation {
+        name.and_then(Collation::lookup)
+            .unwrap_or_else(Collation::binary)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value text is sorted by: its key, or the value itself
+    /// for other storage classes.
+    pub fn sort_key(&self, value: &Value) -> Value {
+        match (&self.key, value) {
+            (Some(key), Value::Text(text)) => Value::Text(key(text)),
+            _ => value.clone(),
+        }
+    }
+
+    /// Compares two values, texts by their keys.
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        match (&self.key, a, b) {
+            (Some(key), Value::Text(a), Value::Text(b)) => key(a).cmp(&key(b)),
+            _ => a.cmp(b),
+        }
+    }
+}
+
+impl fmt::Debug for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Collation").field(&self.name).finish()
+    }
+}
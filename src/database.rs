@@ -0,0 +1,1138 @@
+use std::{io, path::Path};
+
+use crate::HEADER_MAGIC_SIZE;
+use crate::btree::{BTree, BTreeError, IndexKey};
+use crate::json::Json;
+use crate::schema::Rename;
+use crate::token::quote_identifier;
+use crate::{
+    Alteration, Collation, Cursor, ExecuteError, ForeignKey, Index, IndexSchema, OnDelete, Pager,
+    PagerStats, Row, Schema, Table, Value,
+};
+
+/// Page 0 holds the file header, the catalog is rooted right after it.
+const HEADER_PAGE: usize = 0;
+const CATALOG_ROOT_PAGE: usize = 1;
+/// Identifies a database file, stored at the start of the header page.
+const MAGIC: &[u8; HEADER_MAGIC_SIZE] = b"rust-sqlite db 1";
+
+/// A database file holding many tables. The catalog table lists every
+/// table and index with its name, the root page of its B-tree and the
+/// statement that created it.
+pub struct Database {
+    pager: Pager,
+    catalog: Table,
+    tables: Vec<Table>,
+    indexes: Vec<Index>,
+    /// Key of the row added by the last successful `insert`, 0 if none.
+    last_insert_rowid: i64,
+}
+
+impl Database {
+    /// Name of the catalog table, which can be queried like any other table.
+    pub const CATALOG_NAME: &str = "rust_sqlite_schema";
+    const CATALOG_SQL: &str = "create table rust_sqlite_schema (type text(8), name text(64), tbl_name text(64), rootpage integer, sql text(1024))";
+    /// Name of the table recording the largest key handed out to each
+    /// `AUTOINCREMENT` table. It is created along with the first one.
+    pub const SEQUENCE_NAME: &str = "rust_sqlite_sequence";
+    const SEQUENCE_SQL: &str = "create table rust_sqlite_sequence (name text(64), seq integer)";
+    /// Names starting with this prefix are reserved for the tables and
+    /// indexes the database manages itself.
+    pub const RESERVED_PREFIX: &str = "rust_sqlite_";
+    const MAX_NAME_LENGTH: usize = 64;
+    const MAX_SQL_LENGTH: usize = 1024;
+
+    /// Create the database connection. It creates the file in case it doesn't exist.
+    pub fn db_open<P: AsRef<Path>>(filename: P) -> Result<Self, io::Error> {
+        Self::from_pager(Pager::open(filename)?)
+    }
+
+    /// Create a read-only database connection. The file must already exist
+    /// and is never written to, not even when the connection is closed.
+    pub fn db_open_readonly<P: AsRef<Path>>(filename: P) -> Result<Self, io::Error> {
+        Self::from_pager(Pager::open_readonly(filename)?)
+    }
+
+    /// Loads every table listed in the catalog, or lays out a new database
+    /// if the file is empty.
+    fn from_pager(mut pager: Pager) -> Result<Self, io::Error> {
+        let catalog = Table {
+            schema: Schema::parse(Self::CATALOG_SQL).expect("The catalog schema must be valid"),
+            root_page: CATALOG_ROOT_PAGE,
+        };
+
+        if pager.num_pages() == 0 {
+            return Ok(Self::bootstrap(pager, catalog));
+        }
+        if !pager.get_page(HEADER_PAGE).starts_with(MAGIC) {
+            return Err(corrupt_database("file is not a database"));
+        }
+
+        let mut database = Database {
+            pager,
+            catalog,
+            tables: Vec::new(),
+            indexes: Vec::new(),
+            last_insert_rowid: 0,
+        };
+        let entries: Vec<Row> =
+            Cursor::table_start(&mut database.pager, &database.catalog).collect();
+        for entry in entries {
+            let [
+                Value::Text(kind),
+                Value::Text(name),
+                Value::Text(table_name),
+                Value::Integer(root_page),
+                sql,
+            ] = &entry.values[..]
+            else {
+                return Err(corrupt_database("malformed catalog entry"));
+            };
+            let root_page = *root_page as usize;
+            match (kind.as_str(), sql) {
+                ("table", Value::Text(sql)) => {
+                    let schema =
+                        Schema::parse(sql).map_err(|e| corrupt_database(&e.to_string()))?;
+                    database.tables.push(Table { schema, root_page });
+                }
+                // Automatic indexes have no statement: they are numbered after
+                // the table's unique constraints.
+                ("index", Value::Null) => {
+                    let (columns, collations) = database
+                        .tables
+                        .iter()
+                        .find(|table| table.schema.name == *table_name)
+                        .and_then(|table| {
+                            let number = name.rsplit('_').next()?.parse::<usize>().ok()?;
+                            let columns = table.schema.unique.get(number.checked_sub(1)?)?;
+                            Some((
+                                columns.clone(),
+                                Index::column_collations(&table.schema, columns),
+                            ))
+                        })
+                        .ok_or_else(|| corrupt_database("malformed automatic index"))?;
+                    database.indexes.push(Index {
+                        name: name.clone(),
+                        table: table_name.clone(),
+                        columns,
+                        collations,
+                        unique: true,
+                        root_page,
+                        sql: None,
+                    });
+                }
+                ("index", Value::Text(sql)) => {
+                    let definition =
+                        IndexSchema::parse(sql).map_err(|e| corrupt_database(&e.to_string()))?;
+                    let (columns, collations) = database
+                        .tables
+                        .iter()
+                        .find(|table| table.schema.name == definition.table)
+                        .and_then(|table| {
+                            let columns = resolve_columns(&table.schema, &definition.columns)?;
+                            let collations = Index::column_collations(&table.schema, &columns);
+                            Some((columns, collations))
+                        })
+                        .ok_or_else(|| corrupt_database("malformed index"))?;
+                    database.indexes.push(Index {
+                        name: definition.name,
+                        table: definition.table,
+                        columns,
+                        collations,
+                        unique: definition.unique,
+                        root_page,
+                        sql: Some(definition.sql),
+                    });
+                }
+                _ => return Err(corrupt_database("malformed catalog entry")),
+            }
+        }
+
+        Ok(database)
+    }
+
+    /// Writes the header and an empty catalog, then creates the tutorial's
+    /// users table so that a new database is ready to use.
+    fn bootstrap(mut pager: Pager, catalog: Table) -> Self {
+        let header_page = pager
+            .allocate_page()
+            .expect("An empty database has free pages");
+        pager.get_page(header_page)[..MAGIC.len()].copy_from_slice(MAGIC);
+        let catalog_tree =
+            BTree::<i64>::create(&mut pager).expect("An empty database has free pages");
+        debug_assert_eq!(catalog_tree.root_page, catalog.root_page);
+
+        let mut database = Database {
+            pager,
+            catalog,
+            tables: Vec::new(),
+            indexes: Vec::new(),
+            last_insert_rowid: 0,
+        };
+        database
+            .create_table(Schema::users())
+            .expect("The users table fits in an empty database");
+        database
+    }
+
+    /// Closes the database and flushes changes to disk.
+    /// A read-only connection is closed without touching the file.
+    pub fn db_close(mut self) -> io::Result<()> {
+        if self.is_read_only() {
+            return Ok(());
+        }
+        self.pager.flush_all()
+    }
+
+    /// Returns `true` if the connection was opened with `db_open_readonly`.
+    pub fn is_read_only(&self) -> bool {
+        self.pager.is_read_only()
+    }
+
+    /// Returns the pager's I/O counters since the database was opened.
+    pub fn stats(&self) -> PagerStats {
+        self.pager.stats()
+    }
+
+    /// Looks up a table, including the catalog, by name.
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        lookup(&self.catalog, &self.tables, name)
+    }
+
+    /// Returns the key of the row added by the last successful insert on
+    /// this connection, or 0 if nothing was inserted yet.
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.last_insert_rowid
+    }
+
+    /// Returns the user tables, in creation order.
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    /// Returns the indexes, in creation order.
+    pub fn indexes(&self) -> &[Index] {
+        &self.indexes
+    }
+
+    /// Creates an empty table and records it in the catalog, along with an
+    /// automatic index for each of its unique constraints.
+    pub fn create_table(&mut self, schema: Schema) -> Result<(), ExecuteError> {
+        if self.table(&schema.name).is_some() {
+            return Err(ExecuteError::TableExists(schema.name));
+        }
+        if schema.name.len() > Self::MAX_NAME_LENGTH || schema.sql.len() > Self::MAX_SQL_LENGTH {
+            return Err(ExecuteError::SchemaTooLarge);
+        }
+
+        if schema.is_autoincrement() && self.table(Self::SEQUENCE_NAME).is_none() {
+            let sequence = Schema::parse(Self::SEQUENCE_SQL).expect("The sequence schema is valid");
+            self.create_table(sequence)?;
+        }
+
+        let btree = BTree::<i64>::create(&mut self.pager).map_err(execute_error)?;
+        self.add_catalog_entry(
+            "table",
+            &schema.name,
+            &schema.name,
+            btree.root_page,
+            &schema.sql,
+        )?;
+
+        for (number, columns) in schema.unique.iter().enumerate() {
+            let index = Index {
+                name: autoindex_name(&schema.name, number + 1),
+                table: schema.name.clone(),
+                columns: columns.clone(),
+                collations: Index::column_collations(&schema, columns),
+                unique: true,
+                root_page: BTree::<IndexKey>::create(&mut self.pager)
+                    .map_err(execute_error)?
+                    .root_page,
+                sql: None,
+            };
+            self.add_catalog_entry("index", &index.name, &index.table, index.root_page, "")?;
+            self.indexes.push(index);
+        }
+
+        self.tables.push(Table {
+            schema,
+            root_page: btree.root_page,
+        });
+        Ok(())
+    }
+
+    /// Creates an index and fills it with the rows already in its table.
+    /// Creating a unique index fails if those rows hold duplicates.
+    pub fn create_index(&mut self, definition: &IndexSchema) -> Result<(), ExecuteError> {
+        let name_taken = self.table(&definition.name).is_some()
+            || self
+                .indexes
+                .iter()
+                .any(|index| index.name == definition.name);
+        if name_taken {
+            return Err(ExecuteError::IndexExists(definition.name.clone()));
+        }
+        if definition.name.len() > Self::MAX_NAME_LENGTH
+            || definition.sql.len() > Self::MAX_SQL_LENGTH
+        {
+            return Err(ExecuteError::SchemaTooLarge);
+        }
+
+        let table = lookup(&self.catalog, &self.tables, &definition.table)
+            .unwrap_or_else(|| panic!("Unknown table {}", definition.table))
+            .clone();
+        let columns = resolve_columns(&table.schema, &definition.columns)
+            .expect("Indexed columns are checked when the statement is prepared");
+        let index = Index {
+            name: definition.name.clone(),
+            table: definition.table.clone(),
+            collations: Index::column_collations(&table.schema, &columns),
+            columns,
+            unique: definition.unique,
+            root_page: BTree::<IndexKey>::create(&mut self.pager)
+                .map_err(execute_error)?
+                .root_page,
+            sql: Some(definition.sql.clone()),
+        };
+
+        let mut cursor = Cursor::table_start(&mut self.pager, &table);
+        let rows: Vec<(i64, Row)> = std::iter::from_fn(|| cursor.next_with_rowid()).collect();
+        for (rowid, row) in rows {
+            if index.unique && unique_conflict(&mut self.pager, &index, None, &row) {
+                // The index is not in the catalog yet: give its pages back.
+                index.btree().destroy(&mut self.pager);
+                return Err(unique_error(&table.schema, &index, &row));
+            }
+            index
+                .btree()
+                .insert(&mut self.pager, index.key(rowid, &row), Vec::new())
+                .map_err(execute_error)?;
+        }
+
+        self.add_catalog_entry(
+            "index",
+            &index.name,
+            &index.table,
+            index.root_page,
+            &definition.sql,
+        )?;
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    /// Returns the rows of a table, with their rowid, whose `column` holds
+    /// `value`. An index starting with the column is used when there is one,
+    /// the whole table is scanned otherwise.
+    pub fn find(&mut self, table_name: &str, column: usize, value: &Value) -> Vec<(i64, Row)> {
+        let table = lookup(&self.catalog, &self.tables, table_name)
+            .unwrap_or_else(|| panic!("Unknown table {}", table_name));
+        let index = self
+            .indexes
+            .iter()
+            .find(|index| index.table == table_name && index.columns.first() == Some(&column));
+        let Some(index) = index else {
+            let collation = table.schema.columns[column].collation();
+            let mut cursor = Cursor::table_start(&mut self.pager, table);
+            return std::iter::from_fn(|| cursor.next_with_rowid())
+                .filter(|(_, row)| collation.compare(&row.values[column], value).is_eq())
+                .collect();
+        };
+
+        let start = index.entry(vec![value.clone()], i64::MIN);
+        let keys = index.btree().keys_from(&mut self.pager, &start, |key| {
+            key.values[0] == start.values[0]
+        });
+        keys.into_iter()
+            .map(|key| {
+                let payload = table
+                    .btree()
+                    .get(&mut self.pager, &key.rowid)
+                    .expect("Index entries point at rows");
+                (key.rowid, Row::deserialize(&table.schema, &payload))
+            })
+            .collect()
+    }
+
+    /// Records a table or an index in the catalog. An empty statement is
+    /// stored as NULL.
+    fn add_catalog_entry(
+        &mut self,
+        kind: &str,
+        name: &str,
+        table_name: &str,
+        root_page: usize,
+        sql: &str,
+    ) -> Result<(), ExecuteError> {
+        let entry = catalog_entry(kind, name, table_name, root_page, sql);
+        let rowid = next_rowid(&mut self.pager, &self.catalog, 0)?;
+        insert_row(&mut self.pager, &self.catalog, rowid, &entry)
+    }
+
+    /// Rewrites the catalog entries that no longer match the tables and
+    /// indexes they describe, which are matched by root page. Entries keep
+    /// their rowid so that every table is still listed before its indexes.
+    fn update_catalog(&mut self) -> Result<(), ExecuteError> {
+        let mut cursor = Cursor::table_start(&mut self.pager, &self.catalog);
+        let entries: Vec<(i64, Row)> = std::iter::from_fn(|| cursor.next_with_rowid()).collect();
+        for (rowid, entry) in entries {
+            let Value::Integer(root_page) = entry.values[3] else {
+                continue;
+            };
+            let root_page = root_page as usize;
+            let table = self
+                .tables
+                .iter()
+                .find(|table| table.root_page == root_page);
+            let index = self
+                .indexes
+                .iter()
+                .find(|index| index.root_page == root_page);
+            let expected = match (table, index) {
+                (Some(table), _) => catalog_entry(
+                    "table",
+                    &table.schema.name,
+                    &table.schema.name,
+                    root_page,
+                    &table.schema.sql,
+                ),
+                (None, Some(index)) => catalog_entry(
+                    "index",
+                    &index.name,
+                    &index.table,
+                    root_page,
+                    index.sql.as_deref().unwrap_or_default(),
+                ),
+                (None, None) => continue,
+            };
+            if expected != entry {
+                if expected.values[4].to_string().len() > Self::MAX_SQL_LENGTH
+                    || expected.values[1].to_string().len() > Self::MAX_NAME_LENGTH
+                {
+                    return Err(ExecuteError::SchemaTooLarge);
+                }
+                self.catalog.btree().delete(&mut self.pager, &rowid);
+                insert_row(&mut self.pager, &self.catalog, rowid, &expected)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the definition of a table, which `Schema::alter` must accept.
+    /// Only dropping a column rewrites the table's rows: rows written
+    /// before a column was added read back with its default value.
+    pub fn alter_table(
+        &mut self,
+        table_name: &str,
+        alteration: &Alteration,
+    ) -> Result<(), ExecuteError> {
+        let position = self
+            .tables
+            .iter()
+            .position(|table| table.schema.name == table_name)
+            .unwrap_or_else(|| panic!("Unknown table {}", table_name));
+        let table = self.tables[position].clone();
+        let schema = table
+            .schema
+            .alter(alteration)
+            .expect("The alteration was checked when the statement was prepared");
+
+        match alteration {
+            Alteration::AddColumn(_) => {}
+            Alteration::DropColumn(name) => {
+                let dropped = table
+                    .schema
+                    .columns
+                    .iter()
+                    .position(|column| column.name == *name)
+                    .expect("The dropped column exists");
+                let mut cursor = Cursor::table_start(&mut self.pager, &table);
+                let rows: Vec<(i64, Row)> =
+                    std::iter::from_fn(|| cursor.next_with_rowid()).collect();
+                for (rowid, mut row) in rows {
+                    row.values.remove(dropped);
+                    table.btree().delete(&mut self.pager, &rowid);
+                    insert_row(&mut self.pager, &table, rowid, &row)?;
+                }
+                for index in self.indexes.iter_mut().filter(|i| i.table == table_name) {
+                    for column in index.columns.iter_mut().filter(|c| **c > dropped) {
+                        *column -= 1;
+                    }
+                }
+            }
+            Alteration::RenameColumn { from, to } => {
+                let rename = Rename::Column {
+                    table: table_name,
+                    from,
+                    to,
+                };
+                self.rename_references(&rename);
+            }
+            Alteration::RenameTo(to) => {
+                if self.table(to).is_some() {
+                    return Err(ExecuteError::TableExists(to.clone()));
+                }
+                if to.len() > Self::MAX_NAME_LENGTH {
+                    return Err(ExecuteError::SchemaTooLarge);
+                }
+                let rename = Rename::Table {
+                    from: table_name,
+                    to,
+                };
+                self.rename_references(&rename);
+                if let Some((rowid, seq)) = sequence(&mut self.pager, &self.tables, table_name) {
+                    let entry = Row {
+                        values: vec![Value::Text(to.clone()), Value::Integer(seq)],
+                    };
+                    self.update(Self::SEQUENCE_NAME, rowid, &entry)?;
+                }
+                for index in self.indexes.iter_mut().filter(|i| i.table == table_name) {
+                    if index.is_automatic() {
+                        let number = index.name.rsplit('_').next().and_then(|n| n.parse().ok());
+                        index.name =
+                            autoindex_name(to, number.expect("Automatic indexes are numbered"));
+                    }
+                    index.table = to.clone();
+                }
+            }
+        }
+
+        // The statements of the table's indexes name it and its columns.
+        if matches!(
+            alteration,
+            Alteration::RenameColumn { .. } | Alteration::RenameTo(_)
+        ) {
+            for index in self.indexes.iter_mut() {
+                if index.table == schema.name && !index.is_automatic() {
+                    index.sql = Some(index_sql(index, &schema));
+                }
+            }
+        }
+        self.tables[position].schema = schema;
+        self.update_catalog()
+    }
+
+    /// Deletes a table along with its indexes, returning their pages to the
+    /// free list. When other tables reference it, its rows are deleted
+    /// first so that their foreign keys apply their `ON DELETE` action.
+    pub fn drop_table(&mut self, table_name: &str) -> Result<(), ExecuteError> {
+        let position = self
+            .tables
+            .iter()
+            .position(|table| table.schema.name == table_name)
+            .unwrap_or_else(|| panic!("Unknown table {}", table_name));
+        let referenced = self.tables.iter().any(|table| {
+            table.schema.name != table_name
+                && table
+                    .schema
+                    .foreign_keys
+                    .iter()
+                    .any(|fk| fk.parent == table_name)
+        });
+        if referenced {
+            let table = self.tables[position].clone();
+            let mut cursor = Cursor::table_start(&mut self.pager, &table);
+            let rowids: Vec<i64> = std::iter::from_fn(|| cursor.next_with_rowid())
+                .map(|(rowid, _)| rowid)
+                .collect();
+            self.delete(table_name, &rowids)?;
+        }
+
+        if let Some((rowid, _)) = sequence(&mut self.pager, &self.tables, table_name) {
+            self.delete(Self::SEQUENCE_NAME, &[rowid])?;
+        }
+
+        let table = self.tables.remove(position);
+        let (indexes, kept) = std::mem::take(&mut self.indexes)
+            .into_iter()
+            .partition(|index| index.table == table_name);
+        self.indexes = kept;
+        for index in indexes {
+            self.remove_catalog_entry(index.root_page);
+            index.btree().destroy(&mut self.pager);
+        }
+        self.remove_catalog_entry(table.root_page);
+        table.btree().destroy(&mut self.pager);
+        Ok(())
+    }
+
+    /// Deletes an index, returning its pages to the free list.
+    pub fn drop_index(&mut self, index_name: &str) -> Result<(), ExecuteError> {
+        let position = self
+            .indexes
+            .iter()
+            .position(|index| index.name == index_name)
+            .unwrap_or_else(|| panic!("Unknown index {}", index_name));
+        let index = self.indexes.remove(position);
+        self.remove_catalog_entry(index.root_page);
+        index.btree().destroy(&mut self.pager);
+        Ok(())
+    }
+
+    /// Removes the catalog entry of the table or index rooted at `root_page`.
+    fn remove_catalog_entry(&mut self, root_page: usize) {
+        let mut cursor = Cursor::table_start(&mut self.pager, &self.catalog);
+        let rowid = std::iter::from_fn(|| cursor.next_with_rowid())
+            .find(|(_, entry)| entry.values[3] == Value::Integer(root_page as i64))
+            .map(|(rowid, _)| rowid)
+            .expect("Every table and index is in the catalog");
+        self.catalog.btree().delete(&mut self.pager, &rowid);
+    }
+
+    /// Applies a rename to the foreign keys of every table.
+    fn rename_references(&mut self, rename: &Rename) {
+        for table in &mut self.tables {
+            table.schema = table
+                .schema
+                .rename(rename)
+                .expect("Renaming keeps a table definition valid");
+        }
+    }
+
+    /// Inserts a row into a table, after checking it against the table's
+    /// constraints. The row is keyed by its primary key or, if the table has
+    /// none or the key is NULL, by the next free rowid.
+    pub fn insert(&mut self, table_name: &str, row: &Row) -> Result<(), ExecuteError> {
+        self.last_insert_rowid = self.write_row(table_name, None, row)?;
+        Ok(())
+    }
+
+    /// Replaces the row stored under `rowid`, after checking the new row
+    /// against the table's constraints. Changing the primary key moves the
+    /// row to its new key.
+    pub fn update(&mut self, table_name: &str, rowid: i64, row: &Row) -> Result<(), ExecuteError> {
+        self.write_row(table_name, Some(rowid), row).map(drop)
+    }
+
+    /// Writes a new row, or replaces the one stored under `old_rowid`, and
+    /// returns the key it was stored under.
+    fn write_row(
+        &mut self,
+        table_name: &str,
+        old_rowid: Option<i64>,
+        row: &Row,
+    ) -> Result<i64, ExecuteError> {
+        let table = lookup(&self.catalog, &self.tables, table_name)
+            .unwrap_or_else(|| panic!("Unknown table {}", table_name));
+        let autoincrement = table.schema.is_autoincrement();
+        let sequence = if autoincrement {
+            sequence(&mut self.pager, &self.tables, table_name)
+        } else {
+            None
+        };
+        let indexes: Vec<&Index> = self
+            .indexes
+            .iter()
+            .filter(|index| index.table == table.schema.name)
+            .collect();
+
+        let mut row = row.clone();
+        let floor = sequence.map_or(0, |(_, seq)| seq);
+        let rowid = assign_rowid(&mut self.pager, table, old_rowid, floor, &mut row)?;
+        check_constraints(&mut self.pager, table, &indexes, old_rowid, rowid, &row)?;
+
+        // Deferred keys are checked here too: only a delete works out the
+        // whole statement before writing it, so it alone can wait for the
+        // statement to end.
+        for foreign_key in &table.schema.foreign_keys {
+            let value = &row.values[foreign_key.column];
+            // A row may reference itself.
+            let references_itself = foreign_key.parent == table.schema.name
+                && table
+                    .schema
+                    .referenced_column(foreign_key)
+                    .map(|c| &row.values[c])
+                    == Some(value);
+            if !references_itself
+                && !parent_exists(
+                    &mut self.pager,
+                    &self.tables,
+                    &self.indexes,
+                    foreign_key,
+                    value,
+                )
+            {
+                return Err(foreign_key_error());
+            }
+        }
+
+        if let Some(old_rowid) = old_rowid {
+            let btree = table.btree();
+            let payload = btree
+                .get(&mut self.pager, &old_rowid)
+                .unwrap_or_else(|| panic!("Unknown rowid {}", old_rowid));
+            let old_row = Row::deserialize(&table.schema, &payload);
+            check_referenced_key_kept(&mut self.pager, &self.tables, table, &old_row, &row)?;
+            for index in &indexes {
+                index
+                    .btree()
+                    .delete(&mut self.pager, &index.key(old_rowid, &old_row));
+            }
+            btree.delete(&mut self.pager, &old_rowid);
+        }
+
+        insert_row(&mut self.pager, table, rowid, &row)?;
+        for index in &indexes {
+            index
+                .btree()
+                .insert(&mut self.pager, index.key(rowid, &row), Vec::new())
+                .map_err(execute_error)?;
+        }
+
+        if autoincrement && floor < rowid {
+            let entry = Row {
+                values: vec![Value::Text(table_name.to_string()), Value::Integer(rowid)],
+            };
+            let sequence_rowid = sequence.map(|(sequence_rowid, _)| sequence_rowid);
+            self.write_row(Self::SEQUENCE_NAME, sequence_rowid, &entry)?;
+        }
+        Ok(rowid)
+    }
+
+    /// Deletes the rows stored under `rowids`, then applies the `ON DELETE`
+    /// action of every foreign key referencing them. The whole statement is
+    /// worked out before anything is written, so a violated foreign key
+    /// leaves the database unchanged.
+    pub fn delete(&mut self, table_name: &str, rowids: &[i64]) -> Result<(), ExecuteError> {
+        let table = lookup(&self.catalog, &self.tables, table_name)
+            .unwrap_or_else(|| panic!("Unknown table {}", table_name))
+            .clone();
+        let mut pending: Vec<(Table, i64)> = rowids
+            .iter()
+            .rev()
+            .map(|&rowid| (table.clone(), rowid))
+            .collect();
+        let mut deleted: Vec<(Table, i64, Row)> = Vec::new();
+        let mut nulled: Vec<(Table, i64, Row)> = Vec::new();
+        // Rows still referencing a deleted row through a deferred key, with
+        // the referencing column: the statement must delete them or set
+        // the column to NULL before it ends.
+        let mut orphans: Vec<(String, i64, usize)> = Vec::new();
+
+        while let Some((table, rowid)) = pending.pop() {
+            if is_deleted(&deleted, &table.schema.name, rowid) {
+                continue;
+            }
+            let Some(payload) = table.btree().get(&mut self.pager, &rowid) else {
+                continue;
+            };
+            let row = Row::deserialize(&table.schema, &payload);
+            deleted.push((table.clone(), rowid, row.clone()));
+
+            for child in &self.tables {
+                for foreign_key in &child.schema.foreign_keys {
+                    if foreign_key.parent != table.schema.name {
+                        continue;
+                    }
+                    let Some(column) = table.schema.referenced_column(foreign_key) else {
+                        continue;
+                    };
+                    let collation = table.schema.columns[column].collation();
+                    let value = &row.values[column];
+                    for (child_rowid, mut child_row) in
+                        children(&mut self.pager, child, foreign_key, &collation, value)
+                    {
+                        if is_deleted(&deleted, &child.schema.name, child_rowid) {
+                            continue;
+                        }
+                        match foreign_key.on_delete {
+                            OnDelete::Restrict => return Err(foreign_key_error()),
+                            OnDelete::NoAction if !foreign_key.deferred => {
+                                return Err(foreign_key_error());
+                            }
+                            OnDelete::NoAction => {
+                                orphans.push((
+                                    child.schema.name.clone(),
+                                    child_rowid,
+                                    foreign_key.column,
+                                ));
+                            }
+                            OnDelete::Cascade => pending.push((child.clone(), child_rowid)),
+                            OnDelete::SetNull => {
+                                let planned = nulled.iter_mut().find(|(table, rowid, _)| {
+                                    table.schema.name == child.schema.name && *rowid == child_rowid
+                                });
+                                match planned {
+                                    Some((_, _, row)) => {
+                                        row.values[foreign_key.column] = Value::Null;
+                                    }
+                                    None => {
+                                        child_row.values[foreign_key.column] = Value::Null;
+                                        nulled.push((child.clone(), child_rowid, child_row));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        nulled.retain(|(table, rowid, _)| !is_deleted(&deleted, &table.schema.name, *rowid));
+        let orphaned = orphans.iter().any(|(table, rowid, column)| {
+            !is_deleted(&deleted, table, *rowid)
+                && !nulled.iter().any(|(nulled_table, nulled_rowid, row)| {
+                    nulled_table.schema.name == *table
+                        && nulled_rowid == rowid
+                        && matches!(row.values[*column], Value::Null)
+                })
+        });
+        if orphaned {
+            return Err(foreign_key_error());
+        }
+        for (table, rowid, row) in &nulled {
+            let indexes: Vec<&Index> = self
+                .indexes
+                .iter()
+                .filter(|index| index.table == table.schema.name)
+                .collect();
+            check_constraints(&mut self.pager, table, &indexes, Some(*rowid), *rowid, row)?;
+        }
+
+        for (table, rowid, row) in &deleted {
+            for index in self
+                .indexes
+                .iter()
+                .filter(|index| index.table == table.schema.name)
+            {
+                index
+                    .btree()
+                    .delete(&mut self.pager, &index.key(*rowid, row));
+            }
+            table.btree().delete(&mut self.pager, rowid);
+        }
+        for (table, rowid, row) in nulled {
+            self.update(&table.schema.name, rowid, &row)?;
+        }
+        Ok(())
+    }
+
+    /// Creates an iterator over the rows of a table.
+    pub fn table_start(&mut self, table_name: &str) -> Cursor<'_> {
+        let table = lookup(&self.catalog, &self.tables, table_name)
+            .unwrap_or_else(|| panic!("Unknown table {}", table_name));
+        Cursor::table_start(&mut self.pager, table)
+    }
+}
+
+fn autoindex_name(table_name: &str, number: usize) -> String {
+    format!("{}{}_{}", Index::AUTOMATIC_PREFIX, table_name, number)
+}
+
+/// Writes the `create index` statement of `index`, on a table of `schema`.
+fn index_sql(index: &Index, schema: &Schema) -> String {
+    let columns: Vec<String> = index
+        .columns
+        .iter()
+        .map(|&i| quote_identifier(&schema.columns[i].name))
+        .collect();
+    format!(
+        "create {}index {} on {} ({})",
+        if index.unique { "unique " } else { "" },
+        quote_identifier(&index.name),
+        quote_identifier(&index.table),
+        columns.join(", ")
+    )
+}
+
+fn catalog_entry(kind: &str, name: &str, table_name: &str, root_page: usize, sql: &str) -> Row {
+    let sql = match sql {
+        "" => Value::Null,
+        sql => Value::Text(sql.to_string()),
+    };
+    Row {
+        values: vec![
+            Value::Text(kind.to_string()),
+            Value::Text(name.to_string()),
+            Value::Text(table_name.to_string()),
+            Value::Integer(root_page as i64),
+            sql,
+        ],
+    }
+}
+
+fn lookup<'a>(catalog: &'a Table, tables: &'a [Table], name: &str) -> Option<&'a Table> {
+    std::iter::once(catalog)
+        .chain(tables)
+        .find(|table| table.schema.name == name)
+}
+
+/// Returns the key of a row: its primary key, filled in with the next free
+/// rowid when NULL. Rows of tables without one keep the key they were
+/// stored under, or get the next free rowid when new.
+fn assign_rowid(
+    pager: &mut Pager,
+    table: &Table,
+    old_rowid: Option<i64>,
+    floor: i64,
+    row: &mut Row,
+) -> Result<i64, ExecuteError> {
+    let Some(index) = table.schema.primary_key() else {
+        return old_rowid.map_or_else(|| next_rowid(pager, table, floor), Ok);
+    };
+    match row.values[index] {
+        Value::Integer(key) => Ok(key),
+        Value::Null => {
+            let key = next_rowid(pager, table, floor)?;
+            row.values[index] = Value::Integer(key);
+            Ok(key)
+        }
+        _ => Err(ExecuteError::ConstraintViolation(format!(
+            "datatype mismatch: {}.{}",
+            table.schema.name, table.schema.columns[index].name
+        ))),
+    }
+}
+
+/// Returns the key following the largest one in the table and `floor`,
+/// the largest key an `AUTOINCREMENT` table ever handed out.
+fn next_rowid(pager: &mut Pager, table: &Table, floor: i64) -> Result<i64, ExecuteError> {
+    let last = table.btree().last_key(pager).unwrap_or(0);
+    last.max(floor)
+        .checked_add(1)
+        .ok_or(ExecuteError::TableFull)
+}
+
+/// Returns the rowid of the sequence entry of `table_name` and the largest
+/// key recorded in it.
+fn sequence(pager: &mut Pager, tables: &[Table], table_name: &str) -> Option<(i64, i64)> {
+    let sequence = tables
+        .iter()
+        .find(|table| table.schema.name == Database::SEQUENCE_NAME)?;
+    let mut cursor = Cursor::table_start(pager, sequence);
+    std::iter::from_fn(|| cursor.next_with_rowid()).find_map(|(rowid, entry)| {
+        match &entry.values[..] {
+            [Value::Text(name), Value::Integer(seq)] if name == table_name => Some((rowid, *seq)),
+            _ => None,
+        }
+    })
+}
+
+/// Checks a row before anything is written, so that a violation leaves
+/// the table unchanged. `old_rowid` is the row being replaced, if any, which
+/// the new row may not conflict with.
+fn check_constraints(
+    pager: &mut Pager,
+    table: &Table,
+    indexes: &[&Index],
+    old_rowid: Option<i64>,
+    rowid: i64,
+    row: &Row,
+) -> Result<(), ExecuteError> {
+    let schema = &table.schema;
+    let null_column = schema
+        .columns
+        .iter()
+        .zip(&row.values)
+        .find(|(column, value)| column.not_null && matches!(value, Value::Null));
+    if let Some((column, _)) = null_column {
+        return Err(ExecuteError::ConstraintViolation(format!(
+            "NOT NULL constraint failed: {}.{}",
+            schema.name, column.name
+        )));
+    }
+
+    let malformed_json = schema
+        .columns
+        .iter()
+        .zip(&row.values)
+        .find(|(column, value)| {
+            column.is_json() && !matches!(value, Value::Null) && Json::read(value).is_none()
+        });
+    if let Some((column, _)) = malformed_json {
+        return Err(ExecuteError::ConstraintViolation(format!(
+            "JSON constraint failed: {}.{}",
+            schema.name, column.name
+        )));
+    }
+
+    if let Some(check) = schema
+        .checks
+        .iter()
+        .find(|check| !check.is_satisfied(&schema.columns, row))
+    {
+        return Err(ExecuteError::ConstraintViolation(format!(
+            "CHECK constraint failed: {}",
+            check.display_name()
+        )));
+    }
+
+    if old_rowid != Some(rowid) && table.btree().get(pager, &rowid).is_some() {
+        return Err(ExecuteError::DuplicateKey);
+    }
+
+    for index in indexes.iter().filter(|index| index.unique) {
+        if unique_conflict(pager, index, old_rowid, row) {
+            return Err(unique_error(schema, index, row));
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if a row other than `old_rowid` already holds the values
+/// `row` has in the columns of a unique index.
+fn unique_conflict(pager: &mut Pager, index: &Index, old_rowid: Option<i64>, row: &Row) -> bool {
+    // NULLs never conflict: each is distinct from every other value.
+    let key = index.key(i64::MIN, row);
+    if key.values.iter().any(|value| matches!(value, Value::Null)) {
+        return false;
+    }
+    index
+        .btree()
+        .seek(pager, &key)
+        .is_some_and(|found| found.cmp_values(&key).is_eq() && Some(found.rowid) != old_rowid)
+}
+
+fn unique_error(schema: &Schema, index: &Index, row: &Row) -> ExecuteError {
+    let columns: Vec<String> = index
+        .columns
+        .iter()
+        .map(|&i| format!("{}.{}", schema.name, schema.columns[i].name))
+        .collect();
+    let values: Vec<String> = index
+        .columns
+        .iter()
+        .map(|&i| row.values[i].to_string())
+        .collect();
+    ExecuteError::ConstraintViolation(format!(
+        "UNIQUE constraint failed: {} = {}",
+        columns.join(", "),
+        values.join(", ")
+    ))
+}
+
+/// Returns the positions of the named columns in `schema`.
+fn resolve_columns(schema: &Schema, names: &[String]) -> Option<Vec<usize>> {
+    names
+        .iter()
+        .map(|name| schema.columns.iter().position(|c| c.name == *name))
+        .collect()
+}
+
+/// Returns `true` if `value` is NULL or appears in the column referenced by
+/// `foreign_key`, looked up through the parent's primary key or the index
+/// of its unique constraint.
+fn parent_exists(
+    pager: &mut Pager,
+    tables: &[Table],
+    indexes: &[Index],
+    foreign_key: &ForeignKey,
+    value: &Value,
+) -> bool {
+    if matches!(value, Value::Null) {
+        return true;
+    }
+    let Some(parent) = tables
+        .iter()
+        .find(|table| table.schema.name == foreign_key.parent)
+    else {
+        return false;
+    };
+    let Some(column) = parent.schema.referenced_column(foreign_key) else {
+        return false;
+    };
+
+    if parent.schema.primary_key() == Some(column) {
+        return match value {
+            Value::Integer(key) => parent.btree().get(pager, key).is_some(),
+            _ => false,
+        };
+    }
+    let index = indexes.iter().find(|index| {
+        index.table == parent.schema.name && index.unique && index.columns == [column]
+    });
+    match index {
+        Some(index) => {
+            let key = index.entry(vec![value.clone()], i64::MIN);
+            index
+                .btree()
+                .seek(pager, &key)
+                .is_some_and(|found| found.cmp_values(&key).is_eq())
+        }
+        None => false,
+    }
+}
+
+/// Returns `true` if the row of `table` stored under `rowid` is among the
+/// `deleted` rows.
+fn is_deleted(deleted: &[(Table, i64, Row)], table: &str, rowid: i64) -> bool {
+    deleted
+        .iter()
+        .any(|(deleted, deleted_rowid, _)| deleted.schema.name == table && *deleted_rowid == rowid)
+}
+
+/// Returns the rows of `child` whose `foreign_key` column holds `value`,
+/// compared with the `collation` of the parent column.
+fn children(
+    pager: &mut Pager,
+    child: &Table,
+    foreign_key: &ForeignKey,
+    collation: &Collation,
+    value: &Value,
+) -> Vec<(i64, Row)> {
+    if matches!(value, Value::Null) {
+        return Vec::new();
+    }
+    let mut cursor = Cursor::table_start(pager, child);
+    std::iter::from_fn(|| cursor.next_with_rowid())
+        .filter(|(_, row)| {
+            collation
+                .compare(&row.values[foreign_key.column], value)
+                .is_eq()
+        })
+        .collect()
+}
+
+/// Fails if an update of `parent` changes a key that rows still reference.
+fn check_referenced_key_kept(
+    pager: &mut Pager,
+    tables: &[Table],
+    parent: &Table,
+    old_row: &Row,
+    row: &Row,
+) -> Result<(), ExecuteError> {
+    for child in tables {
+        for foreign_key in &child.schema.foreign_keys {
+            if foreign_key.parent != parent.schema.name {
+                continue;
+            }
+            let Some(column) = parent.schema.referenced_column(foreign_key) else {
+                continue;
+            };
+            let old_value = &old_row.values[column];
+            let collation = parent.schema.columns[column].collation();
+            if collation.compare(old_value, &row.values[column]).is_ne()
+                && !children(pager, child, foreign_key, &collation, old_value).is_empty()
+            {
+                return Err(foreign_key_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn foreign_key_error() -> ExecuteError {
+    ExecuteError::ConstraintViolation("FOREIGN KEY constraint failed".to_string())
+}
+
+fn insert_row(pager: &mut Pager, table: &Table, rowid: i64, row: &Row) -> Result<(), ExecuteError> {
+    table
+        .btree()
+        .insert(pager, rowid, row.serialize())
+        .map_err(execute_error)
+}
+
+fn execute_error(err: BTreeError) -> ExecuteError {
+    match err {
+        BTreeError::Full => ExecuteError::TableFull,
+        BTreeError::PayloadTooLarge => ExecuteError::RowTooLarge,
+        BTreeError::DuplicateKey => ExecuteError::DuplicateKey,
+    }
+}
+
+fn corrupt_database(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Corrupt database: {}", reason),
+    )
+}
@@ -0,0 +1,399 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Value;
+
+/// The date and time functions. Each takes a time value followed by
+/// modifiers, except `strftime` whose first argument is the format.
+pub(crate) const FUNCTIONS: [&str; 6] = [
+    "date",
+    "time",
+    "datetime",
+    "julianday",
+    "unixepoch",
+    "strftime",
+];
+
+const MS_PER_SECOND: i64 = 1000;
+const MS_PER_MINUTE: i64 = 60 * MS_PER_SECOND;
+const MS_PER_HOUR: i64 = 60 * MS_PER_MINUTE;
+const MS_PER_DAY: i64 = 24 * MS_PER_HOUR;
+/// Julian day of 1970-01-01 00:00:00, in milliseconds.
+const UNIX_EPOCH_MS: i64 = 210_866_760_000_000;
+/// Julian day of 9999-12-31 23:59:59.999, the last moment that can be
+/// represented, in milliseconds. The first is -4713-11-24 12:00:00.
+const MAX_MS: i64 = 464_269_060_799_999;
+
+/// A moment in time, as milliseconds since the start of the Julian day
+/// calendar. Times are always UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Moment(i64);
+
+/// A moment broken down into calendar fields.
+struct Fields {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    /// Milliseconds into the minute.
+    millis: i64,
+}
+
+impl Moment {
+    fn now() -> Moment {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("The clock is set after 1970");
+        Moment(UNIX_EPOCH_MS + elapsed.as_millis() as i64)
+    }
+
+    fn from_julian_day(day: f64) -> Option<Moment> {
+        Moment::checked(millis(day * MS_PER_DAY as f64)?)
+    }
+
+    fn from_unix_seconds(seconds: f64) -> Option<Moment> {
+        Moment::checked(UNIX_EPOCH_MS + millis(seconds * MS_PER_SECOND as f64)?)
+    }
+
+    fn checked(ms: i64) -> Option<Moment> {
+        (0..=MAX_MS).contains(&ms).then_some(Moment(ms))
+    }
+
+    /// Converts a proleptic Gregorian date and time of day to a moment,
+    /// carrying days past the end of their month into the next one.
+    fn from_fields(fields: &Fields) -> Option<Moment> {
+        let (mut year, mut month) = (fields.year, fields.month);
+        if month <= 2 {
+            year -= 1;
+            month += 12;
+        }
+        // Years far outside the range of moments overflow on the way.
+        let century = year / 100;
+        let leap_days = 2 - century + century / 4;
+        let year_days = year.checked_add(4716)?.checked_mul(36525)? / 100;
+        let days = year_days + 306001 * (month + 1) / 10000;
+        let day = days.checked_add(fields.day + leap_days - 1524)?;
+        let time = fields.hour * MS_PER_HOUR + fields.minute * MS_PER_MINUTE + fields.millis;
+        Moment::checked(
+            day.checked_mul(MS_PER_DAY)?
+                .checked_add(time - MS_PER_DAY / 2)?,
+        )
+    }
+
+    fn fields(self) -> Fields {
+        let day = (self.0 + MS_PER_DAY / 2) / MS_PER_DAY;
+        let alpha = ((day as f64 - 1867216.25) / 36524.25) as i64;
+        let a = day + 1 + alpha - alpha / 4;
+        let b = a + 1524;
+        let c = ((b as f64 - 122.1) / 365.25) as i64;
+        let d = 36525 * (c & 32767) / 100;
+        let e = ((b - d) as f64 / 30.6001) as i64;
+        let month = if e < 14 { e - 1 } else { e - 13 };
+
+        let time = (self.0 + MS_PER_DAY / 2) % MS_PER_DAY;
+        Fields {
+            year: if month > 2 { c - 4716 } else { c - 4715 },
+            month,
+            day: b - d - (30.6001 * e as f64) as i64,
+            hour: time / MS_PER_HOUR,
+            minute: time % MS_PER_HOUR / MS_PER_MINUTE,
+            millis: time % MS_PER_MINUTE,
+        }
+    }
+
+    fn julian_day(self) -> f64 {
+        self.0 as f64 / MS_PER_DAY as f64
+    }
+
+    fn unix_seconds(self) -> i64 {
+        (self.0 - UNIX_EPOCH_MS).div_euclid(MS_PER_SECOND)
+    }
+
+    /// Day of the week, 0 for Sunday.
+    fn weekday(self) -> i64 {
+        (self.0 + MS_PER_DAY * 3 / 2) / MS_PER_DAY % 7
+    }
+
+    /// Start of the day the moment falls on.
+    fn start_of_day(self) -> Moment {
+        Moment((self.0 + MS_PER_DAY / 2) / MS_PER_DAY * MS_PER_DAY - MS_PER_DAY / 2)
+    }
+}
+
+/// Evaluates one of the date and time `FUNCTIONS`. Any NULL argument, time
+/// value that cannot be read or unknown modifier yields NULL.
+pub(crate) fn function(name: &str, args: &[Value]) -> Value {
+    let (format, args) = match name {
+        "strftime" => match args.split_first() {
+            Some((Value::Null, _)) | None => return Value::Null,
+            Some((format, args)) => (Some(format.to_string()), args),
+        },
+        _ => (None, args),
+    };
+    let Some(moment) = moment(args) else {
+        return Value::Null;
+    };
+
+    let fields = moment.fields();
+    let date = format!("{:04}-{:02}-{:02}", fields.year, fields.month, fields.day);
+    let time = format!(
+        "{:02}:{:02}:{:02}",
+        fields.hour,
+        fields.minute,
+        fields.millis / MS_PER_SECOND
+    );
+    match name {
+        "date" => Value::Text(date),
+        "time" => Value::Text(time),
+        "datetime" => Value::Text(format!("{} {}", date, time)),
+        "julianday" => Value::Real(moment.julian_day()),
+        "unixepoch" => Value::Integer(moment.unix_seconds()),
+        "strftime" => format
+            .and_then(|format| strftime(&format, moment))
+            .map_or(Value::Null, Value::Text),
+        name => unreachable!("Unknown date function {}", name),
+    }
+}
+
+/// Reads a time value and applies the modifiers that follow it. Without
+/// arguments the time value is `'now'`.
+fn moment(args: &[Value]) -> Option<Moment> {
+    let Some((value, modifiers)) = args.split_first() else {
+        return Some(Moment::now());
+    };
+    // A number is a Julian day unless the first modifier says otherwise.
+    let number = match value {
+        Value::Null => return None,
+        Value::Integer(i) => Some(*i as f64),
+        Value::Real(r) => Some(*r),
+        Value::Text(text) => number(text),
+        Value::Blob(_) => None,
+    };
+    let mut moment = match (number, value) {
+        (Some(number), _) => Moment::from_julian_day(number),
+        (None, Value::Text(text)) => parse(text),
+        (None, _) => None,
+    };
+
+    for (i, modifier) in modifiers.iter().enumerate() {
+        if matches!(modifier, Value::Null) {
+            return None;
+        }
+        let modifier = modifier.to_string().trim().to_ascii_lowercase();
+        let number = number.filter(|_| i == 0);
+        moment = match (modifier.as_str(), number) {
+            ("unixepoch", Some(number)) => Moment::from_unix_seconds(number),
+            ("julianday", Some(_)) => moment,
+            // Numbers that cannot be a Julian day are read as unix time.
+            ("auto", Some(number)) => moment.or_else(|| Moment::from_unix_seconds(number)),
+            (modifier, _) => apply(modifier, moment?),
+        };
+    }
+    moment
+}
+
+/// Parses ISO-8601 text: `YYYY-MM-DD`, optionally followed by a time of
+/// day after a space or a `T`, a time of day alone on 2000-01-01, or
+/// `now`. A time of day is `HH:MM[:SS[.SSS]]`, optionally followed by `Z`
+/// or a `±HH:MM` offset from UTC.
+fn parse(text: &str) -> Option<Moment> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("now") {
+        return Some(Moment::now());
+    }
+
+    let mut fields = Fields {
+        year: 2000,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        millis: 0,
+    };
+    let time = match digits(text, 4) {
+        Some(year) if text[4..].starts_with('-') => {
+            let month = digits(&text[5..], 2).filter(|month| (1..=12).contains(month))?;
+            let day = text[7..].strip_prefix('-').and_then(|rest| digits(rest, 2));
+            fields.year = year;
+            fields.month = month;
+            fields.day = day.filter(|day| (1..=31).contains(day))?;
+            let rest = &text[10..];
+            match rest.strip_prefix(['T', 't', ' ']) {
+                Some(time) => time.trim_start(),
+                None if rest.is_empty() => return Moment::from_fields(&fields),
+                None => return None,
+            }
+        }
+        _ => text,
+    };
+
+    let hour = digits(time, 2).filter(|hour| *hour <= 24)?;
+    let minute = time[2..]
+        .strip_prefix(':')
+        .and_then(|rest| digits(rest, 2))
+        .filter(|minute| *minute < 60)?;
+    let mut rest = &time[5..];
+    let mut millis = 0;
+    if let Some(after) = rest.strip_prefix(':') {
+        let end = after
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(after.len());
+        let seconds = after[..end].parse::<f64>().ok().filter(|s| *s < 60.0)?;
+        if end < 2 || after[..end].ends_with('.') {
+            return None;
+        }
+        millis = (seconds * MS_PER_SECOND as f64).round() as i64;
+        rest = &after[end..];
+    }
+    fields.hour = hour;
+    fields.minute = minute;
+    fields.millis = millis;
+    let moment = Moment::from_fields(&fields)?;
+
+    // The time was given in another zone: move it to UTC.
+    let offset = match rest.trim_start() {
+        "" | "Z" | "z" => 0,
+        zone => {
+            let sign = match zone.chars().next()? {
+                '+' => 1,
+                '-' => -1,
+                _ => return None,
+            };
+            let hours = digits(&zone[1..], 2).filter(|hours| *hours <= 14)?;
+            let minutes = zone[3..]
+                .strip_prefix(':')
+                .filter(|minutes| minutes.len() == 2)
+                .and_then(|minutes| digits(minutes, 2))
+                .filter(|minutes| *minutes < 60)?;
+            sign * (hours * MS_PER_HOUR + minutes * MS_PER_MINUTE)
+        }
+    };
+    Moment::checked(moment.0 - offset)
+}
+
+/// Reads exactly `count` ASCII digits at the start of `text`.
+fn digits(text: &str, count: usize) -> Option<i64> {
+    let prefix = text.get(..count)?;
+    if !prefix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    prefix.parse().ok()
+}
+
+/// Applies a modifier: `±N days`, `hours`, `minutes`, `seconds`,
+/// `months` or `years`, `start of day`, `month` or `year`, or `weekday N`,
+/// which moves forward to the next day that is the Nth of the week.
+fn apply(modifier: &str, moment: Moment) -> Option<Moment> {
+    let mut fields = moment.fields();
+    if let Some(unit) = modifier.strip_prefix("start of ") {
+        fields.hour = 0;
+        fields.minute = 0;
+        fields.millis = 0;
+        match unit {
+            "day" => {}
+            "month" => fields.day = 1,
+            "year" => {
+                fields.month = 1;
+                fields.day = 1;
+            }
+            _ => return None,
+        }
+        return Moment::from_fields(&fields);
+    }
+    if let Some(weekday) = modifier.strip_prefix("weekday ") {
+        let weekday = weekday.trim().parse::<i64>().ok()?;
+        if !(0..7).contains(&weekday) {
+            return None;
+        }
+        let days = (weekday - moment.weekday()).rem_euclid(7);
+        return Moment::checked(moment.0 + days * MS_PER_DAY);
+    }
+
+    let (amount, unit) = modifier.split_once(char::is_whitespace)?;
+    let amount = number(amount)?;
+    let unit = unit.trim();
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+    let ms = match unit {
+        "day" => MS_PER_DAY,
+        "hour" => MS_PER_HOUR,
+        "minute" => MS_PER_MINUTE,
+        "second" => MS_PER_SECOND,
+        "month" | "year" => {
+            // Whole months move the calendar date, keeping the day of the
+            // month, so that Jan 31 + 1 month overflows into March.
+            // Too many months to count saturate, then fail to add up.
+            let months = if unit == "year" { 12.0 } else { 1.0 } * amount.trunc();
+            let month = (fields.month - 1).checked_add(months as i64)?;
+            fields.year = fields.year.checked_add(month.div_euclid(12))?;
+            fields.month = month.rem_euclid(12) + 1;
+            let moment = Moment::from_fields(&fields)?;
+            let days = if unit == "year" { 365.0 } else { 30.0 } * amount.fract();
+            return Moment::checked(moment.0 + millis(days * MS_PER_DAY as f64)?);
+        }
+        _ => return None,
+    };
+    Moment::checked(moment.0 + millis(amount * ms as f64)?)
+}
+
+/// Rounds a number of milliseconds, or `None` if it is further from zero
+/// than any two moments are apart.
+fn millis(ms: f64) -> Option<i64> {
+    (ms.abs() <= MAX_MS as f64).then(|| ms.round() as i64)
+}
+
+/// Reads a number as SQL does, so that words like `nan` or `inf` are not
+/// numbers.
+fn number(text: &str) -> Option<f64> {
+    match Value::parse_literal(text.trim()) {
+        Value::Integer(i) => Some(i as f64),
+        Value::Real(r) => Some(r),
+        _ => None,
+    }
+}
+
+/// Formats a moment: `%d` day of month, `%f` seconds with milliseconds,
+/// `%H` hour, `%j` day of year, `%J` Julian day, `%m` month, `%M` minute,
+/// `%s` unix time, `%S` seconds, `%w` day of week (0 for Sunday), `%u` day
+/// of week (7 for Sunday), `%Y` year, `%F` as `%Y-%m-%d`, `%T` as
+/// `%H:%M:%S` and `%%` a percent sign. Other conversions yield `None`.
+fn strftime(format: &str, moment: Moment) -> Option<String> {
+    let fields = moment.fields();
+    let mut output = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        let converted = match chars.next()? {
+            'd' => format!("{:02}", fields.day),
+            'f' => format!("{:06.3}", fields.millis as f64 / MS_PER_SECOND as f64),
+            'H' => format!("{:02}", fields.hour),
+            'j' => {
+                let start = apply("start of year", moment)?.start_of_day();
+                format!(
+                    "{:03}",
+                    (moment.start_of_day().0 - start.0) / MS_PER_DAY + 1
+                )
+            }
+            'J' => Value::Real(moment.julian_day()).to_string(),
+            'm' => format!("{:02}", fields.month),
+            'M' => format!("{:02}", fields.minute),
+            's' => moment.unix_seconds().to_string(),
+            'S' => format!("{:02}", fields.millis / MS_PER_SECOND),
+            'w' => moment.weekday().to_string(),
+            'u' => match moment.weekday() {
+                0 => 7,
+                weekday => weekday,
+            }
+            .to_string(),
+            'Y' => format!("{:04}", fields.year),
+            'F' => strftime("%Y-%m-%d", moment)?,
+            'T' => strftime("%H:%M:%S", moment)?,
+            '%' => "%".to_string(),
+            _ => return None,
+        };
+        output.push_str(&converted);
+    }
+    Some(output)
+}
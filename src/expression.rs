@@ -0,0 +1,463 @@
+use std::ops::RangeInclusive;
+
+use crate::parser::Parser;
+use crate::{Collation, Column, PrepareError, Row, Value, datetime, json};
+
+/// An SQL expression, as found in `WHERE` clauses, `CHECK` constraints and
+/// `DEFAULT` values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// `<operand> COLLATE <collation>`: the operand's value, compared with
+    /// the named collation.
+    Collate {
+        operand: Box<Expr>,
+        collation: String,
+    },
+    /// `<operand> IN (<expression>, ...)`.
+    In {
+        operand: Box<Expr>,
+        list: Vec<Expr>,
+    },
+    /// `<operand> BETWEEN <low> AND <high>`.
+    Between {
+        operand: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Plus,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Is,
+    IsNot,
+    Like,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Concat,
+    /// `->`: the element of a JSON value at a path, as JSON.
+    JsonExtract,
+    /// `->>`: the element of a JSON value at a path, as an SQL value.
+    JsonExtractValue,
+}
+
+/// Scalar functions, with the number of arguments each takes.
+pub(crate) const FUNCTIONS: [(&str, RangeInclusive<usize>); 13] = [
+    ("length", 1..=1),
+    ("lower", 1..=1),
+    ("upper", 1..=1),
+    ("date", 0..=usize::MAX),
+    ("time", 0..=usize::MAX),
+    ("datetime", 0..=usize::MAX),
+    ("julianday", 0..=usize::MAX),
+    ("unixepoch", 0..=usize::MAX),
+    ("strftime", 1..=usize::MAX),
+    ("json", 1..=1),
+    ("json_extract", 2..=usize::MAX),
+    ("json_set", 3..=usize::MAX),
+    ("json_array_length", 1..=2),
+];
+
+/// Keywords that read the current time, with the function they stand for.
+pub(crate) const CURRENT_TIME_KEYWORDS: [(&str, &str); 3] = [
+    ("current_date", "date"),
+    ("current_time", "time"),
+    ("current_timestamp", "datetime"),
+];
+
+impl Expr {
+    /// Parses an expression, which must span the whole input.
+    pub fn parse(input: &str) -> Result<Expr, PrepareError> {
+        Parser::new(input).parse_expression()
+    }
+
+    /// Names of the columns the expression reads.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.visit_columns(&mut columns);
+        columns
+    }
+
+    fn visit_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Column(name) => columns.push(name),
+            Expr::Unary { operand, .. } => operand.visit_columns(columns),
+            Expr::Binary { left, right, .. } => {
+                left.visit_columns(columns);
+                right.visit_columns(columns);
+            }
+            Expr::Function { args, .. } => args.iter().for_each(|arg| arg.visit_columns(columns)),
+            Expr::Collate { operand, .. } => operand.visit_columns(columns),
+            Expr::In { operand, list } => {
+                operand.visit_columns(columns);
+                list.iter().for_each(|item| item.visit_columns(columns));
+            }
+            Expr::Between { operand, low, high } => {
+                operand.visit_columns(columns);
+                low.visit_columns(columns);
+                high.visit_columns(columns);
+            }
+        }
+    }
+
+    /// Names of the collations the expression names with `COLLATE`.
+    pub fn collations(&self) -> Vec<&str> {
+        let mut collations = Vec::new();
+        self.visit_collations(&mut collations);
+        collations
+    }
+
+    fn visit_collations<'a>(&'a self, collations: &mut Vec<&'a str>) {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) => {}
+            Expr::Unary { operand, .. } => operand.visit_collations(collations),
+            Expr::Binary { left, right, .. } => {
+                left.visit_collations(collations);
+                right.visit_collations(collations);
+            }
+            Expr::Function { args, .. } => {
+                args.iter().for_each(|arg| arg.visit_collations(collations))
+            }
+            Expr::Collate { operand, collation } => {
+                collations.push(collation);
+                operand.visit_collations(collations);
+            }
+            Expr::In { operand, list } => {
+                operand.visit_collations(collations);
+                list.iter()
+                    .for_each(|item| item.visit_collations(collations));
+            }
+            Expr::Between { operand, low, high } => {
+                operand.visit_collations(collations);
+                low.visit_collations(collations);
+                high.visit_collations(collations);
+            }
+        }
+    }
+
+    /// Returns the collation the expression's values sort by: the one named
+    /// by a `COLLATE`, else that of the column it reads, else `BINARY`.
+    pub fn collation(&self, columns: &[Column]) -> Collation {
+        Collation::named(
+            self.explicit_collation()
+                .or_else(|| self.column_collation(columns)),
+        )
+    }
+
+    fn explicit_collation(&self) -> Option<&str> {
+        match self {
+            Expr::Collate { collation, .. } => Some(collation),
+            _ => None,
+        }
+    }
+
+    fn column_collation<'a>(&self, columns: &'a [Column]) -> Option<&'a str> {
+        match self {
+            Expr::Column(name) => Some(
+                columns
+                    .iter()
+                    .find(|column| column.name == *name)
+                    .and_then(|column| column.collation.as_deref())
+                    .unwrap_or(Collation::BINARY),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the expression yields JSON text, which JSON
+    /// functions take as JSON rather than as a string.
+    fn is_json(&self) -> bool {
+        match self {
+            Expr::Function { name, .. } => {
+                matches!(name.as_str(), "json" | "json_set" | "json_extract")
+            }
+            Expr::Binary { op, .. } => *op == BinaryOp::JsonExtract,
+            _ => false,
+        }
+    }
+
+    /// Evaluates the expression against a row of a table with `columns`.
+    pub fn evaluate(&self, columns: &[Column], row: &Row) -> Value {
+        match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Column(name) => columns
+                .iter()
+                .position(|column| column.name == *name)
+                .map_or(Value::Null, |i| row.values[i].clone()),
+            Expr::Unary { op, operand } => {
+                let value = operand.evaluate(columns, row);
+                match op {
+                    UnaryOp::Plus => value,
+                    UnaryOp::Negate => match numeric(&value) {
+                        Value::Integer(i) => i
+                            .checked_neg()
+                            .map_or(Value::Real(-(i as f64)), Value::Integer),
+                        Value::Real(r) => Value::Real(-r),
+                        _ => Value::Null,
+                    },
+                    UnaryOp::Not => from_truth(truth(&value).map(|b| !b)),
+                }
+            }
+            Expr::Binary { op, left, right } => {
+                let collation = match op {
+                    BinaryOp::Eq
+                    | BinaryOp::NotEq
+                    | BinaryOp::Is
+                    | BinaryOp::IsNot
+                    | BinaryOp::Lt
+                    | BinaryOp::LtEq
+                    | BinaryOp::Gt
+                    | BinaryOp::GtEq => comparison_collation(left, right, columns),
+                    _ => Collation::binary(),
+                };
+                let left = left.evaluate(columns, row);
+                let right = right.evaluate(columns, row);
+                binary(*op, left, right, &collation)
+            }
+            Expr::Function { name, args } if json::FUNCTIONS.contains(&name.as_str()) => {
+                let is_json: Vec<bool> = args.iter().map(Expr::is_json).collect();
+                let args: Vec<Value> = args.iter().map(|arg| arg.evaluate(columns, row)).collect();
+                json::function(name, &args, &is_json)
+            }
+            Expr::Function { name, args } => {
+                let args: Vec<Value> = args.iter().map(|arg| arg.evaluate(columns, row)).collect();
+                function(name, args)
+            }
+            Expr::Collate { operand, .. } => operand.evaluate(columns, row),
+            // True if an item equals the operand, else NULL if the operand or
+            // an item is NULL.
+            Expr::In { operand, list } => {
+                let value = operand.evaluate(columns, row);
+                let mut found = Some(false);
+                for item in list {
+                    let collation = comparison_collation(operand, item, columns);
+                    let equal = binary(
+                        BinaryOp::Eq,
+                        value.clone(),
+                        item.evaluate(columns, row),
+                        &collation,
+                    );
+                    match truth(&equal) {
+                        Some(true) => return from_truth(Some(true)),
+                        Some(false) => {}
+                        None => found = None,
+                    }
+                }
+                from_truth(found)
+            }
+            Expr::Between { operand, low, high } => {
+                let value = operand.evaluate(columns, row);
+                let above = binary(
+                    BinaryOp::GtEq,
+                    value.clone(),
+                    low.evaluate(columns, row),
+                    &comparison_collation(operand, low, columns),
+                );
+                let below = binary(
+                    BinaryOp::LtEq,
+                    value,
+                    high.evaluate(columns, row),
+                    &comparison_collation(operand, high, columns),
+                );
+                binary(BinaryOp::And, above, below, &Collation::binary())
+            }
+        }
+    }
+}
+
+/// Collation of a comparison: the one named by a `COLLATE` on the left
+/// operand, else on the right one, else that of a column on the left, else
+/// of a column on the right, and `BINARY` otherwise.
+fn comparison_collation(left: &Expr, right: &Expr, columns: &[Column]) -> Collation {
+    let name = left
+        .explicit_collation()
+        .or_else(|| right.explicit_collation())
+        .or_else(|| left.column_collation(columns))
+        .or_else(|| right.column_collation(columns));
+    Collation::named(name)
+}
+
+/// Three-valued truth of a value: NULL is unknown, numbers are true when
+/// they are not zero and text is read as the number it starts with.
+pub fn truth(value: &Value) -> Option<bool> {
+    match numeric(value) {
+        Value::Integer(i) => Some(i != 0),
+        Value::Real(r) => Some(r != 0.0),
+        _ => None,
+    }
+}
+
+fn from_truth(truth: Option<bool>) -> Value {
+    truth.map_or(Value::Null, |b| Value::Integer(b as i64))
+}
+
+/// Converts a value to a number for arithmetic: text and blobs become the
+/// number they start with, or 0.
+fn numeric(value: &Value) -> Value {
+    match value {
+        Value::Null | Value::Integer(_) | Value::Real(_) => value.clone(),
+        Value::Text(text) => number_prefix(text),
+        Value::Blob(bytes) => number_prefix(&String::from_utf8_lossy(bytes)),
+    }
+}
+
+fn number_prefix(text: &str) -> Value {
+    let text = text.trim_start();
+    (1..=text.len())
+        .rev()
+        .filter(|&end| text.is_char_boundary(end))
+        .find_map(|end| match Value::parse_literal(&text[..end]) {
+            value @ (Value::Integer(_) | Value::Real(_)) => Some(value),
+            _ => None,
+        })
+        .unwrap_or(Value::Integer(0))
+}
+
+fn binary(op: BinaryOp, left: Value, right: Value, collation: &Collation) -> Value {
+    let ordering = || collation.compare(&left, &right);
+    match op {
+        BinaryOp::And => match (truth(&left), truth(&right)) {
+            (Some(false), _) | (_, Some(false)) => from_truth(Some(false)),
+            (Some(true), Some(true)) => from_truth(Some(true)),
+            _ => Value::Null,
+        },
+        BinaryOp::Or => match (truth(&left), truth(&right)) {
+            (Some(true), _) | (_, Some(true)) => from_truth(Some(true)),
+            (Some(false), Some(false)) => from_truth(Some(false)),
+            _ => Value::Null,
+        },
+        BinaryOp::Is => from_truth(Some(ordering().is_eq())),
+        BinaryOp::IsNot => from_truth(Some(ordering().is_ne())),
+        _ if matches!(left, Value::Null) || matches!(right, Value::Null) => Value::Null,
+        BinaryOp::Eq => from_truth(Some(ordering().is_eq())),
+        BinaryOp::NotEq => from_truth(Some(ordering().is_ne())),
+        BinaryOp::Lt => from_truth(Some(ordering().is_lt())),
+        BinaryOp::LtEq => from_truth(Some(ordering().is_le())),
+        BinaryOp::Gt => from_truth(Some(ordering().is_gt())),
+        BinaryOp::GtEq => from_truth(Some(ordering().is_ge())),
+        BinaryOp::Like => from_truth(Some(like(&text(&right), &text(&left)))),
+        BinaryOp::Concat => Value::Text(format!("{}{}", text(&left), text(&right))),
+        BinaryOp::JsonExtract => json::arrow(&left, &right, false),
+        BinaryOp::JsonExtractValue => json::arrow(&left, &right, true),
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            arithmetic(op, numeric(&left), numeric(&right))
+        }
+    }
+}
+
+/// Text of a value as used by string operators: blobs are read as UTF-8.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Blob(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        value => value.to_string(),
+    }
+}
+
+/// Integer arithmetic that overflows, and any arithmetic on a real, is done
+/// in floating point. Dividing by zero yields NULL.
+fn arithmetic(op: BinaryOp, left: Value, right: Value) -> Value {
+    if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+        let result = match op {
+            BinaryOp::Add => a.checked_add(*b),
+            BinaryOp::Sub => a.checked_sub(*b),
+            BinaryOp::Mul => a.checked_mul(*b),
+            BinaryOp::Div if *b == 0 => return Value::Null,
+            BinaryOp::Div => a.checked_div(*b),
+            BinaryOp::Rem if *b == 0 => return Value::Null,
+            BinaryOp::Rem => a.checked_rem(*b),
+            _ => unreachable!("Not an arithmetic operator"),
+        };
+        if let Some(result) = result {
+            return Value::Integer(result);
+        }
+    }
+
+    let as_real = |value: &Value| match value {
+        Value::Integer(i) => *i as f64,
+        Value::Real(r) => *r,
+        _ => 0.0,
+    };
+    let (a, b) = (as_real(&left), as_real(&right));
+    match op {
+        BinaryOp::Add => Value::Real(a + b),
+        BinaryOp::Sub => Value::Real(a - b),
+        BinaryOp::Mul => Value::Real(a * b),
+        _ if b == 0.0 => Value::Null,
+        BinaryOp::Div => Value::Real(a / b),
+        BinaryOp::Rem => Value::Real(a % b),
+        _ => unreachable!("Not an arithmetic operator"),
+    }
+}
+
+/// Matches `text` against a LIKE pattern, where `%` matches any sequence of
+/// characters and `_` any single one. ASCII letters match in either case.
+fn like(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // Positions of the text that the pattern consumed so far can end at.
+    let mut reachable = vec![false; text.len() + 1];
+    reachable[0] = true;
+    for p in &pattern {
+        let mut next = vec![false; text.len() + 1];
+        for i in 0..=text.len() {
+            if !reachable[i] {
+                continue;
+            }
+            match p {
+                '%' => next[i..].fill(true),
+                '_' if i < text.len() => next[i + 1] = true,
+                c if i < text.len() && c.eq_ignore_ascii_case(&text[i]) => next[i + 1] = true,
+                _ => {}
+            }
+        }
+        reachable = next;
+    }
+    reachable[text.len()]
+}
+
+fn function(name: &str, mut args: Vec<Value>) -> Value {
+    if datetime::FUNCTIONS.contains(&name) {
+        return datetime::function(name, &args);
+    }
+    match (name, args.swap_remove(0)) {
+        (_, Value::Null) => Value::Null,
+        ("length", Value::Blob(bytes)) => Value::Integer(bytes.len() as i64),
+        ("length", value) => Value::Integer(value.to_string().chars().count() as i64),
+        ("lower", value) => Value::Text(text(&value).to_lowercase()),
+        ("upper", value) => Value::Text(text(&value).to_uppercase()),
+        (name, _) => unreachable!("Unknown function {} passed the parser", name),
+    }
+}
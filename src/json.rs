@@ -0,0 +1,600 @@
+use std::fmt;
+
+use crate::{Schema, Value};
+
+/// The JSON functions, with `json_each` handled by `select` as a table.
+pub(crate) const FUNCTIONS: [&str; 4] = ["json", "json_extract", "json_set", "json_array_length"];
+
+/// A parsed JSON value. Object members keep the order they were written in.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// One step of a path: `.key`, `[N]` or `[#-N]`, counting from the end.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+    FromEnd(usize),
+}
+
+impl Json {
+    /// Parses JSON text, which must hold a single value.
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut parser = Parser {
+            input: text.as_bytes(),
+            position: 0,
+        };
+        let json = parser.value()?;
+        parser.skip_whitespace();
+        (parser.position == parser.input.len()).then_some(json)
+    }
+
+    /// Reads a value as JSON: text is parsed, other values convert as
+    /// `from_value` does. Returns `None` for malformed text and blobs.
+    pub fn read(value: &Value) -> Option<Json> {
+        match value {
+            Value::Text(text) => Json::parse(text),
+            value => Json::from_value(value),
+        }
+    }
+
+    /// Converts an SQL value to JSON, text becoming a string.
+    fn from_value(value: &Value) -> Option<Json> {
+        match value {
+            Value::Null => Some(Json::Null),
+            Value::Integer(i) => Some(Json::Integer(*i)),
+            Value::Real(r) => Some(Json::Real(*r)),
+            Value::Text(text) => Some(Json::String(text.clone())),
+            Value::Blob(_) => None,
+        }
+    }
+
+    /// Converts to an SQL value: booleans become 1 and 0, arrays and
+    /// objects their JSON text.
+    pub fn to_value(&self) -> Value {
+        match self {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Integer(*b as i64),
+            Json::Integer(i) => Value::Integer(*i),
+            Json::Real(r) => Value::Real(*r),
+            Json::String(text) => Value::Text(text.clone()),
+            Json::Array(_) | Json::Object(_) => Value::Text(self.to_string()),
+        }
+    }
+
+    /// Name of the JSON type, as reported by `json_each`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(true) => "true",
+            Json::Bool(false) => "false",
+            Json::Integer(_) => "integer",
+            Json::Real(_) => "real",
+            Json::String(_) => "text",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// Returns the element at `path`, `None` if the path is malformed or
+    /// leads nowhere.
+    pub fn get(&self, path: &str) -> Option<&Json> {
+        parse_path(path)?
+            .iter()
+            .try_fold(self, |json, step| json.child(step))
+    }
+
+    fn child(&self, step: &Step) -> Option<&Json> {
+        match (self, step) {
+            (Json::Object(members), Step::Key(key)) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, json)| json),
+            (Json::Array(elements), Step::Index(i)) => elements.get(*i),
+            (Json::Array(elements), Step::FromEnd(n)) => {
+                elements.get(elements.len().checked_sub(*n)?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets the element at `path`, adding it if its parent exists but does
+    /// not have it yet: a missing object member, or the array element right
+    /// past the end. Returns `false` if the path is malformed.
+    fn set(&mut self, path: &str, value: Json) -> bool {
+        let Some(steps) = parse_path(path) else {
+            return false;
+        };
+        let Some((last, parents)) = steps.split_last() else {
+            *self = value;
+            return true;
+        };
+        let mut json = self;
+        for step in parents {
+            json = match json.child_mut(step) {
+                Some(child) => child,
+                None => return true,
+            };
+        }
+        match (json, last) {
+            (Json::Object(members), Step::Key(key)) => {
+                match members.iter_mut().find(|(name, _)| name == key) {
+                    Some((_, member)) => *member = value,
+                    None => members.push((key.clone(), value)),
+                }
+            }
+            (Json::Array(elements), step) => {
+                let i = match step {
+                    Step::Index(i) => Some(*i),
+                    Step::FromEnd(n) => elements.len().checked_sub(*n),
+                    Step::Key(_) => None,
+                };
+                match i {
+                    Some(i) if i < elements.len() => elements[i] = value,
+                    Some(i) if i == elements.len() => elements.push(value),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn child_mut(&mut self, step: &Step) -> Option<&mut Json> {
+        match (self, step) {
+            (Json::Object(members), Step::Key(key)) => members
+                .iter_mut()
+                .find(|(name, _)| name == key)
+                .map(|(_, json)| json),
+            (Json::Array(elements), Step::Index(i)) => elements.get_mut(*i),
+            (Json::Array(elements), Step::FromEnd(n)) => {
+                let i = elements.len().checked_sub(*n)?;
+                elements.get_mut(i)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Minified JSON text.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Integer(i) => write!(f, "{}", i),
+            Json::Real(r) if r.is_nan() => write!(f, "null"),
+            Json::Real(r) if r.is_infinite() => {
+                write!(f, "{}9e999", if *r < 0.0 { "-" } else { "" })
+            }
+            Json::Real(r) => write!(f, "{:?}", r),
+            Json::String(text) => write_string(f, text),
+            Json::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, member)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", member)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            '\u{8}' => write!(f, "\\b")?,
+            '\u{c}' => write!(f, "\\f")?,
+            c if c.is_control() && (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Parses a path: `$` followed by `.key`, `."key"`, `[N]`, `[#]` or
+/// `[#-N]` steps.
+fn parse_path(path: &str) -> Option<Vec<Step>> {
+    let mut rest = path.strip_prefix('$')?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let (key, length) = match after.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.find('"')?;
+                    (&quoted[..end], end + 2)
+                }
+                None => {
+                    let end = after.find(['.', '[']).unwrap_or(after.len());
+                    (&after[..end], end)
+                }
+            };
+            if key.is_empty() && length == 0 {
+                return None;
+            }
+            steps.push(Step::Key(key.to_string()));
+            rest = &after[length..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            let index = &after[..end];
+            steps.push(match index.strip_prefix('#') {
+                Some("") => Step::FromEnd(0),
+                Some(n) => Step::FromEnd(n.strip_prefix('-')?.trim().parse().ok()?),
+                None => Step::Index(index.trim().parse().ok()?),
+            });
+            rest = &after[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(steps)
+}
+
+/// Returns the path of the member `key` of the element at `path`, quoting
+/// keys that are not plain words.
+fn member_path(path: &str, key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}.\"{}\"", path, key)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.position)
+            .is_some_and(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+        {
+            self.position += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let matches = self.input.get(self.position) == Some(&byte);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn literal(&mut self, word: &str, json: Json) -> Option<Json> {
+        let end = self.position + word.len();
+        (self.input.get(self.position..end) == Some(word.as_bytes())).then(|| {
+            self.position = end;
+            json
+        })
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        match self.input.get(self.position)? {
+            b'n' => self.literal("null", Json::Null),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => {
+                self.position += 1;
+                let mut elements = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        elements.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Json::Array(elements))
+            }
+            b'{' => {
+                self.position += 1;
+                let mut members = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let name = self.string()?;
+                        if !self.eat(b':') {
+                            return None;
+                        }
+                        members.push((name, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Json::Object(members))
+            }
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.position;
+        let digits = |parser: &mut Self| {
+            let from = parser.position;
+            while parser
+                .input
+                .get(parser.position)
+                .is_some_and(u8::is_ascii_digit)
+            {
+                parser.position += 1;
+            }
+            parser.position - from
+        };
+
+        if self.input[self.position] == b'-' {
+            self.position += 1;
+        }
+        let integer_start = self.position;
+        let integer_digits = digits(self);
+        if integer_digits == 0 || (integer_digits > 1 && self.input[integer_start] == b'0') {
+            return None;
+        }
+        let mut is_integer = true;
+        if self.input.get(self.position) == Some(&b'.') {
+            self.position += 1;
+            is_integer = false;
+            if digits(self) == 0 {
+                return None;
+            }
+        }
+        if matches!(self.input.get(self.position), Some(b'e' | b'E')) {
+            self.position += 1;
+            is_integer = false;
+            if matches!(self.input.get(self.position), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            if digits(self) == 0 {
+                return None;
+            }
+        }
+
+        let text = std::str::from_utf8(&self.input[start..self.position]).ok()?;
+        match text.parse::<i64>() {
+            Ok(i) if is_integer => Some(Json::Integer(i)),
+            _ => text.parse::<f64>().ok().map(Json::Real),
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.input.get(self.position) != Some(&b'"') {
+            return None;
+        }
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self.input.get(self.position)?;
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self.input.get(self.position)?;
+                    self.position += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte if byte < 0x20 => return None,
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).ok()
+    }
+
+    /// Reads the hex digits of a `\u` escape, combining surrogate pairs.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let hex = |parser: &mut Self| {
+            let digits = parser.input.get(parser.position..parser.position + 4)?;
+            parser.position += 4;
+            u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+        };
+        let high = hex(self)?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high);
+        }
+        if self.input.get(self.position..self.position + 2) != Some(b"\\u") {
+            return None;
+        }
+        self.position += 2;
+        let low = hex(self).filter(|low| (0xDC00..0xE000).contains(low))?;
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+    }
+}
+
+/// Evaluates one of the JSON `FUNCTIONS`. Arguments marked in `is_json`
+/// hold JSON produced by another JSON function or operator, which
+/// `json_set` inserts as JSON rather than as a string. Malformed JSON or
+/// paths yield NULL.
+pub(crate) fn function(name: &str, args: &[Value], is_json: &[bool]) -> Value {
+    let Some(json) = args.first().filter(|value| !matches!(value, Value::Null)) else {
+        return Value::Null;
+    };
+    let Some(mut json) = Json::read(json) else {
+        return Value::Null;
+    };
+    let text = |value: &Value| match value {
+        Value::Text(text) => Some(text.clone()),
+        _ => None,
+    };
+
+    match name {
+        "json" => Value::Text(json.to_string()),
+        "json_extract" => match &args[1..] {
+            [path] => text(path)
+                .and_then(|path| json.get(&path).map(Json::to_value))
+                .unwrap_or(Value::Null),
+            // Several paths extract an array of the elements found.
+            paths => {
+                let elements: Option<Vec<Json>> = paths
+                    .iter()
+                    .map(|path| Some(json.get(&text(path)?).cloned().unwrap_or(Json::Null)))
+                    .collect();
+                elements.map_or(Value::Null, |elements| {
+                    Value::Text(Json::Array(elements).to_string())
+                })
+            }
+        },
+        "json_set" => {
+            for (pair, json_flags) in args[1..].chunks(2).zip(is_json[1..].chunks(2)) {
+                let [path, value] = pair else {
+                    return Value::Null;
+                };
+                let value = if json_flags[1] {
+                    Json::read(value).or_else(|| Json::from_value(value))
+                } else {
+                    Json::from_value(value)
+                };
+                let (Some(path), Some(value)) = (text(path), value) else {
+                    return Value::Null;
+                };
+                if !json.set(&path, value) {
+                    return Value::Null;
+                }
+            }
+            Value::Text(json.to_string())
+        }
+        "json_array_length" => {
+            let element = match args.get(1) {
+                Some(path) => text(path).and_then(|path| json.get(&path)),
+                None => Some(&json),
+            };
+            match element {
+                Some(Json::Array(elements)) => Value::Integer(elements.len() as i64),
+                Some(_) => Value::Integer(0),
+                None => Value::Null,
+            }
+        }
+        name => unreachable!("Unknown JSON function {}", name),
+    }
+}
+
+/// Evaluates `json -> path` or, with `as_value`, `json ->> path`. The path
+/// may also be a key or an array index. `->` returns JSON text, `->>` the
+/// element as an SQL value.
+pub(crate) fn arrow(json: &Value, path: &Value, as_value: bool) -> Value {
+    let path = match path {
+        Value::Text(path) if path.starts_with('$') => path.clone(),
+        Value::Text(key) => member_path("$", key),
+        Value::Integer(i) => format!("$[{}]", i),
+        _ => return Value::Null,
+    };
+    let Some(json) = Json::read(json) else {
+        return Value::Null;
+    };
+    match json.get(&path) {
+        Some(element) if as_value => element.to_value(),
+        Some(element) => Value::Text(element.to_string()),
+        None => Value::Null,
+    }
+}
+
+/// The table `json_each(json [, path])` produces: one row per element of
+/// the array or member of the object at `path`, or a single row if the
+/// element is neither.
+const EACH_SQL: &str =
+    "create table json_each (key, value, type text, atom, fullkey text, path text)";
+
+/// Returns the schema of the rows of `json_each`.
+pub(crate) fn each_schema() -> Schema {
+    Schema::parse(EACH_SQL).expect("The json_each schema is valid")
+}
+
+/// Returns the rows of `json_each` over `json` at `path`.
+pub(crate) fn each(json: &Value, path: Option<&Value>) -> Vec<Vec<Value>> {
+    let path = match path {
+        None => "$".to_string(),
+        Some(Value::Text(path)) => path.clone(),
+        Some(_) => return Vec::new(),
+    };
+    let Some(json) = Json::read(json) else {
+        return Vec::new();
+    };
+    let Some(element) = json.get(&path) else {
+        return Vec::new();
+    };
+
+    let row = |key: Value, element: &Json, fullkey: String| {
+        let atom = match element {
+            Json::Array(_) | Json::Object(_) => Value::Null,
+            element => element.to_value(),
+        };
+        vec![
+            key,
+            element.to_value(),
+            Value::Text(element.type_name().to_string()),
+            atom,
+            Value::Text(fullkey),
+            Value::Text(path.clone()),
+        ]
+    };
+    match element {
+        Json::Array(elements) => elements
+            .iter()
+            .enumerate()
+            .map(|(i, element)| {
+                row(
+                    Value::Integer(i as i64),
+                    element,
+                    format!("{}[{}]", path, i),
+                )
+            })
+            .collect(),
+        Json::Object(members) => members
+            .iter()
+            .map(|(key, member)| row(Value::Text(key.clone()), member, member_path(&path, key)))
+            .collect(),
+        element => vec![row(Value::Null, element, path.clone())],
+    }
+}
@@ -1,15 +1,85 @@
+pub mod ast;
+mod btree;
+mod collation;
+mod database;
+mod datetime;
+mod expression;
+mod json;
+mod pager;
+mod parser;
+mod row;
+mod schema;
+mod statement;
+mod table;
+mod token;
+mod value;
+
+pub use collation::Collation;
+pub use database::Database;
+pub use expression::{BinaryOp, Expr, UnaryOp};
+pub use pager::{Pager, PagerStats};
+pub use parser::parse;
+pub use row::Row;
+pub use schema::{Alteration, Check, Column, ForeignKey, IndexSchema, OnDelete, Schema};
+pub use statement::{ExecuteError, PrepareError, Source, Statement};
+pub use table::{Cursor, Index, Table};
+pub use token::{Lexer, Token, TokenKind, tokenize};
+pub use value::{Affinity, Value};
+
 pub const COLUMN_USERNAME_SIZE: usize = 32;
 pub const COLUMN_EMAIL_SIZE: usize = 255;
-pub const ID_SIZE: usize = size_of::<u32>();
+pub const ID_SIZE: usize = size_of::<i64>();
 pub const USERNAME_SIZE: usize = COLUMN_USERNAME_SIZE;
 pub const EMAIL_SIZE: usize = COLUMN_EMAIL_SIZE;
 
-pub const ID_OFFSET: usize = 0;
-pub const USERNAME_OFFSET: usize = ID_OFFSET + ID_SIZE;
-pub const EMAIL_OFFSET: usize = USERNAME_OFFSET + USERNAME_SIZE;
-pub const ROW_SIZE: usize = ID_SIZE + USERNAME_SIZE + EMAIL_SIZE;
+/// Header of the largest record of the default users table: the header size
+/// and the serial types of the id, the username and the email.
+const USERS_RECORD_HEADER_SIZE: usize = 1 + 1 + 1 + 2;
+/// Size of the largest row of the default users table when its text is
+/// ASCII. Other characters take up to four bytes each.
+pub const ROW_SIZE: usize = USERS_RECORD_HEADER_SIZE + ID_SIZE + USERNAME_SIZE + EMAIL_SIZE;
 
 pub const PAGE_SIZE: usize = 4096;
 pub const TABLE_MAX_PAGES: usize = 100;
-pub const ROWS_PER_PAGE: usize = PAGE_SIZE / ROW_SIZE;
-pub const TABLE_MAX_ROWS: usize = ROWS_PER_PAGE * TABLE_MAX_PAGES;
+/// Number of pages fetched in one read once the pager detects a sequential scan.
+pub const READ_AHEAD_PAGES: usize = 8;
+
+/// File header layout, in the first page: a magic string identifying the
+/// file, then the first page of the free list and the number of free pages.
+pub const HEADER_MAGIC_SIZE: usize = 16;
+pub const FREE_LIST_HEAD_SIZE: usize = size_of::<u32>();
+pub const FREE_LIST_HEAD_OFFSET: usize = HEADER_MAGIC_SIZE;
+pub const FREE_PAGE_COUNT_OFFSET: usize = FREE_LIST_HEAD_OFFSET + FREE_LIST_HEAD_SIZE;
+
+/// Common node header layout.
+pub const NODE_TYPE_SIZE: usize = size_of::<u8>();
+pub const NODE_TYPE_OFFSET: usize = 0;
+pub const NUM_CELLS_SIZE: usize = size_of::<u16>();
+pub const NUM_CELLS_OFFSET: usize = NODE_TYPE_OFFSET + NODE_TYPE_SIZE;
+/// Right-most child of an internal node, next leaf of a leaf node.
+pub const RIGHT_POINTER_SIZE: usize = size_of::<u32>();
+pub const RIGHT_POINTER_OFFSET: usize = NUM_CELLS_OFFSET + NUM_CELLS_SIZE;
+pub const NODE_HEADER_SIZE: usize = NODE_TYPE_SIZE + NUM_CELLS_SIZE + RIGHT_POINTER_SIZE;
+
+/// Leaf node cell layout: the key, the payload length and the payload.
+pub const LEAF_CELL_KEY_SIZE: usize = size_of::<i64>();
+pub const LEAF_CELL_LENGTH_SIZE: usize = size_of::<u16>();
+pub const LEAF_CELL_HEADER_SIZE: usize = LEAF_CELL_KEY_SIZE + LEAF_CELL_LENGTH_SIZE;
+
+/// Internal node cell layout: the child page and the largest key stored under it.
+pub const INTERNAL_CELL_CHILD_SIZE: usize = size_of::<u32>();
+pub const INTERNAL_CELL_KEY_SIZE: usize = size_of::<i64>();
+pub const INTERNAL_CELL_SIZE: usize = INTERNAL_CELL_CHILD_SIZE + INTERNAL_CELL_KEY_SIZE;
+
+/// Largest payload a leaf cell can hold. Keeping cells under a third of a
+/// node guarantees that splitting an overflowing node yields two nodes that fit.
+pub const MAX_PAYLOAD_SIZE: usize = (PAGE_SIZE - NODE_HEADER_SIZE) / 3 - LEAF_CELL_HEADER_SIZE;
+
+/// Rows of the default users table that fit in a leaf node when each
+/// takes `ROW_SIZE` bytes. Shorter rows pack more densely.
+pub const ROWS_PER_PAGE: usize =
+    (PAGE_SIZE - NODE_HEADER_SIZE) / (LEAF_CELL_HEADER_SIZE + ROW_SIZE);
+/// Capacity of the default users table when rows of `ROW_SIZE` bytes are
+/// appended in key order: every page but the file header, the catalog and
+/// the users root is a full leaf.
+pub const TABLE_MAX_ROWS: usize = ROWS_PER_PAGE * (TABLE_MAX_PAGES - 3);
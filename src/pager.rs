@@ -0,0 +1,305 @@
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    FREE_LIST_HEAD_OFFSET, FREE_PAGE_COUNT_OFFSET, PAGE_SIZE, READ_AHEAD_PAGES, TABLE_MAX_PAGES,
+};
+
+/// The page holding the file header, which records the free list.
+const HEADER_PAGE: usize = 0;
+
+/// I/O counters collected by the pager since the database was opened.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PagerStats {
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub pages_read: u64,
+    pub pages_written: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub fsyncs: u64,
+    /// Pages dropped from the cache. The cache holds every page up to
+    /// `TABLE_MAX_PAGES`, so nothing is evicted yet.
+    pub evictions: u64,
+}
+
+impl PagerStats {
+    /// Returns the counters accumulated since the `earlier` snapshot,
+    /// e.g. the I/O caused by a single statement.
+    pub fn since(&self, earlier: &PagerStats) -> PagerStats {
+        PagerStats {
+            cache_hits: self.cache_hits - earlier.cache_hits,
+            cache_misses: self.cache_misses - earlier.cache_misses,
+            pages_read: self.pages_read - earlier.pages_read,
+            pages_written: self.pages_written - earlier.pages_written,
+            bytes_read: self.bytes_read - earlier.bytes_read,
+            bytes_written: self.bytes_written - earlier.bytes_written,
+            fsyncs: self.fsyncs - earlier.fsyncs,
+            evictions: self.evictions - earlier.evictions,
+        }
+    }
+
+    /// Lists every counter with a human readable name, in display order.
+    pub fn counters(&self) -> [(&'static str, u64); 8] {
+        [
+            ("cache hits", self.cache_hits),
+            ("cache misses", self.cache_misses),
+            ("pages read", self.pages_read),
+            ("pages written", self.pages_written),
+            ("bytes read", self.bytes_read),
+            ("bytes written", self.bytes_written),
+            ("fsyncs", self.fsyncs),
+            ("evictions", self.evictions),
+        ]
+    }
+}
+
+/// Manages reading and writing pages from the database file.
+/// Implements an in-memory cache to reduce disk I/O.
+pub struct Pager {
+    file: File,
+    file_length: u64,
+    /// Number of pages in the database, including the ones allocated but
+    /// not yet flushed to the file.
+    num_pages: usize,
+    pages: [Option<Box<[u8; PAGE_SIZE]>>; TABLE_MAX_PAGES],
+    read_only: bool,
+    /// The page right after the last one loaded from disk. A miss on this
+    /// page means the caller is scanning sequentially.
+    next_sequential_page: Option<usize>,
+    stats: PagerStats,
+}
+
+impl Pager {
+    /// Opens a database file and returns a new Pager instance.
+    pub fn open<P: AsRef<Path>>(filename: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600) // S_IWUSR | S_IRUSR
+            .open(filename)?;
+
+        Self::from_file(file, false)
+    }
+
+    /// Opens an existing database file with read permission only.
+    /// Any attempt to write a page back to the file fails.
+    pub fn open_readonly<P: AsRef<Path>>(filename: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).open(filename)?;
+
+        Self::from_file(file, true)
+    }
+
+    fn from_file(mut file: File, read_only: bool) -> io::Result<Self> {
+        let file_length = file.seek(SeekFrom::End(0))?;
+        let num_pages = (file_length as usize).div_ceil(PAGE_SIZE);
+        if num_pages > TABLE_MAX_PAGES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "database file is larger than the maximum number of pages",
+            ));
+        }
+        let pages = std::array::from_fn(|_| None);
+
+        Ok(Self {
+            file,
+            file_length,
+            num_pages,
+            pages,
+            read_only,
+            next_sequential_page: None,
+            stats: PagerStats::default(),
+        })
+    }
+
+    /// Returns `true` if the file was opened without write permission.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns the number of pages in the database.
+    pub fn num_pages(&self) -> usize {
+        self.num_pages
+    }
+
+    /// Returns how many pages can still be allocated: the pages on the free
+    /// list and the ones that can be appended.
+    pub fn free_page_count(&mut self) -> usize {
+        TABLE_MAX_PAGES - self.num_pages + self.free_list().1
+    }
+
+    /// Returns a zeroed page, taken from the free list or else appended to
+    /// the database, or `None` if every page is in use and the database
+    /// already holds `TABLE_MAX_PAGES` pages.
+    pub fn allocate_page(&mut self) -> Option<usize> {
+        let (head, count) = self.free_list();
+        if head != 0 {
+            // A free page starts with the number of the next free page.
+            let page = self.get_page(head);
+            let next = read_u32(page, 0) as usize;
+            page.fill(0);
+            self.set_free_list(next, count - 1);
+            return Some(head);
+        }
+        if self.num_pages >= TABLE_MAX_PAGES {
+            return None;
+        }
+
+        let page_num = self.num_pages;
+        self.num_pages += 1;
+        self.pages[page_num] = Some(Box::new([0u8; PAGE_SIZE]));
+        Some(page_num)
+    }
+
+    /// Puts a page that is no longer used on the free list, so that the
+    /// next allocation reuses it instead of growing the file.
+    pub fn free_page(&mut self, page_num: usize) {
+        assert!(page_num != HEADER_PAGE, "The header page cannot be freed");
+        let (head, count) = self.free_list();
+        let page = self.get_page(page_num);
+        page.fill(0);
+        write_u32(page, 0, head as u32);
+        self.set_free_list(page_num, count + 1);
+    }
+
+    /// Returns the first page of the free list, 0 if it is empty, and the
+    /// number of pages on it.
+    fn free_list(&mut self) -> (usize, usize) {
+        if self.num_pages == 0 {
+            return (0, 0);
+        }
+        let header = self.get_page(HEADER_PAGE);
+        (
+            read_u32(header, FREE_LIST_HEAD_OFFSET) as usize,
+            read_u32(header, FREE_PAGE_COUNT_OFFSET) as usize,
+        )
+    }
+
+    fn set_free_list(&mut self, head: usize, count: usize) {
+        let header = self.get_page(HEADER_PAGE);
+        write_u32(header, FREE_LIST_HEAD_OFFSET, head as u32);
+        write_u32(header, FREE_PAGE_COUNT_OFFSET, count as u32);
+    }
+
+    /// Returns a snapshot of the I/O counters.
+    pub fn stats(&self) -> PagerStats {
+        self.stats
+    }
+
+    /// Retrieves a page from the pager's cache or loads it from the file.
+    /// When the miss continues a sequential scan, the following pages are
+    /// read ahead in the same read so the next misses are served from cache.
+    ///
+    /// Read-ahead goes by page number, not by the B-tree's leaf chain: a
+    /// table whose leaves are out of order in the file, e.g. after splits
+    /// caused by inserting keys in descending order or after reusing pages
+    /// from the free list, is scanned one page per miss.
+    pub fn get_page(&mut self, page_num: usize) -> &mut [u8; PAGE_SIZE] {
+        assert!(page_num < TABLE_MAX_PAGES, "Page number out of bounds");
+
+        if self.pages[page_num].is_some() {
+            self.stats.cache_hits += 1;
+        } else {
+            // Cache miss. Allocate memory and load from file.
+            self.stats.cache_misses += 1;
+            let num_pages_on_disk = (self.file_length as usize).div_ceil(PAGE_SIZE);
+
+            if page_num < num_pages_on_disk {
+                let window = if self.next_sequential_page == Some(page_num) {
+                    READ_AHEAD_PAGES
+                } else {
+                    1
+                };
+                let num_pages = (page_num..num_pages_on_disk)
+                    .take(window)
+                    .take_while(|&i| i == page_num || self.pages[i].is_none())
+                    .count();
+                self.load_pages(page_num, num_pages);
+            } else {
+                self.pages[page_num] = Some(Box::new([0u8; PAGE_SIZE]));
+            }
+        }
+
+        self.pages[page_num]
+            .as_mut()
+            .expect("Accessing to not existing page.")
+    }
+
+    /// Reads `num_pages` consecutive pages starting at `first_page` with a
+    /// single seek and read, and stores them in the cache.
+    fn load_pages(&mut self, first_page: usize, num_pages: usize) {
+        let start = first_page * PAGE_SIZE;
+        let bytes_to_read = std::cmp::min(self.file_length as usize - start, num_pages * PAGE_SIZE);
+        let mut buffer = vec![0u8; num_pages * PAGE_SIZE];
+
+        self.file
+            .seek(io::SeekFrom::Start(start as u64))
+            .expect("Unable to set page offset in file.");
+        self.file
+            .read_exact(&mut buffer[..bytes_to_read])
+            .expect("Unable to read the page from file.");
+        self.stats.pages_read += num_pages as u64;
+        self.stats.bytes_read += bytes_to_read as u64;
+
+        for (i, chunk) in buffer.chunks_exact(PAGE_SIZE).enumerate() {
+            let mut page = Box::new([0u8; PAGE_SIZE]);
+            page.copy_from_slice(chunk);
+            self.pages[first_page + i] = Some(page);
+        }
+        self.next_sequential_page = Some(first_page + num_pages);
+    }
+
+    /// Writes a page to the file.
+    pub fn flush_page(&mut self, page_num: usize) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "database is opened read-only",
+            ));
+        }
+
+        if self.pages[page_num].is_none() {
+            panic!("Tried to flush a null page: {}", page_num);
+        }
+
+        self.file
+            .seek(SeekFrom::Start((page_num * PAGE_SIZE) as u64))?;
+        if let Some(page) = self.pages[page_num].as_ref() {
+            self.file.write_all(&page[..])?;
+            self.stats.pages_written += 1;
+            self.stats.bytes_written += PAGE_SIZE as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes all cached pages to disk before closing.
+    pub fn flush_all(&mut self) -> io::Result<()> {
+        for page_num in 0..TABLE_MAX_PAGES {
+            if self.pages[page_num].is_some() {
+                self.flush_page(page_num)?;
+            }
+        }
+
+        self.file.sync_all()?;
+        self.stats.fsyncs += 1;
+        Ok(())
+    }
+}
+
+fn read_u32(page: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; size_of::<u32>()];
+    bytes.copy_from_slice(&page[offset..offset + size_of::<u32>()]);
+    u32::from_le_bytes(bytes)
+}
+
+fn write_u32(page: &mut [u8], offset: usize, value: u32) {
+    page[offset..offset + size_of::<u32>()].copy_from_slice(&value.to_le_bytes());
+}
@@ -0,0 +1,919 @@
+use crate::ast::{
+    ColumnConstraint, ColumnDef, CreateIndex, CreateTable, Delete, Insert, OrderBy, References,
+    ResultColumn, Select, Statement, TableConstraint, TableRef, Update, Values,
+};
+use crate::expression::{CURRENT_TIME_KEYWORDS, FUNCTIONS};
+use crate::token::{Lexer, Token, TokenKind};
+use crate::{Alteration, BinaryOp, Check, Expr, OnDelete, PrepareError, UnaryOp, Value};
+
+/// Parses one SQL statement, optionally followed by a semicolon.
+pub fn parse(sql: &str) -> Result<Statement, PrepareError> {
+    let mut parser = Parser::new(sql);
+    let statement = parser.statement()?;
+    parser.symbol(&[";"]);
+    parser.expect_end("the end of the statement")?;
+    Ok(statement)
+}
+
+/// A recursive-descent parser over the tokens of a statement. Expressions
+/// have one function per precedence level, from the loosest binding
+/// operator to the tightest.
+pub(crate) struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    /// Why the lexer stopped before the end of the input, if it did.
+    error: Option<PrepareError>,
+    /// Where the lexer stopped: the end of the input or an invalid token.
+    stop: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(input: &'a str) -> Parser<'a> {
+        let mut parser = Parser {
+            input,
+            tokens: Vec::new(),
+            position: 0,
+            error: None,
+            stop: 0,
+        };
+        parser.read_tokens(Lexer::new(input));
+        parser
+    }
+
+    /// Replaces the tokens to parse with those `lexer` reads.
+    fn read_tokens(&mut self, mut lexer: Lexer) {
+        self.tokens.clear();
+        self.position = 0;
+        self.error = None;
+        for token in lexer.by_ref() {
+            match token {
+                Ok(token) => self.tokens.push(token),
+                Err(err) => self.error = Some(err),
+            }
+        }
+        self.stop = lexer.offset();
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<&TokenKind> {
+        self.tokens
+            .get(self.position + ahead)
+            .map(|token| &token.kind)
+    }
+
+    fn next(&mut self) -> Option<TokenKind> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Offset of the next token, or of where the lexer stopped.
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.stop, |token| token.offset)
+    }
+
+    /// End of the last token read.
+    fn previous_end(&self) -> usize {
+        self.tokens[self.position - 1].end
+    }
+
+    /// The error for a missing `expected`, naming the next token. Past the
+    /// last token, the error that stopped the lexer takes precedence.
+    fn error(&self, expected: &str) -> PrepareError {
+        match (self.peek(), &self.error) {
+            (None, Some(error)) => error.clone(),
+            (found, _) => PrepareError::SyntaxError {
+                expected: expected.to_string(),
+                found: found.map(TokenKind::to_string),
+                offset: self.offset(),
+            },
+        }
+    }
+
+    fn expect_end(&self, expected: &str) -> Result<(), PrepareError> {
+        match (self.peek(), &self.error) {
+            (None, None) => Ok(()),
+            _ => Err(self.error(expected)),
+        }
+    }
+
+    /// Consumes the next token if it is the keyword `keyword`.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let matches = matches!(self.peek(), Some(TokenKind::Keyword(word)) if *word == keyword);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), PrepareError> {
+        match self.keyword(keyword) {
+            true => Ok(()),
+            false => Err(self.error(&format!("'{}'", keyword))),
+        }
+    }
+
+    /// Consumes the next token if it is the name `word`, for words such as
+    /// `key` or `deferrable` that are only keywords in some places.
+    fn word(&mut self, word: &str) -> bool {
+        let matches = matches!(self.peek(), Some(TokenKind::Identifier(name)) if name.eq_ignore_ascii_case(word));
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), PrepareError> {
+        match self.word(word) {
+            true => Ok(()),
+            false => Err(self.error(&format!("'{}'", word))),
+        }
+    }
+
+    /// Consumes the next token if it is one of `symbols`.
+    fn symbol(&mut self, symbols: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(TokenKind::Symbol(symbol)) if symbols.contains(symbol) => {
+                let symbol = *symbol;
+                self.position += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), PrepareError> {
+        match self.symbol(&[symbol]) {
+            Some(_) => Ok(()),
+            None => Err(self.error(&format!("'{}'", symbol))),
+        }
+    }
+
+    /// Reads a name, described as `what` if it is missing.
+    fn identifier(&mut self, what: &str) -> Result<String, PrepareError> {
+        match self.peek() {
+            Some(TokenKind::Identifier(name)) => {
+                let name = name.clone();
+                self.position += 1;
+                Ok(name)
+            }
+            _ => Err(self.error(what)),
+        }
+    }
+
+    /// Reads `(<name>, ...)`.
+    fn identifier_list(&mut self, what: &str) -> Result<Vec<String>, PrepareError> {
+        self.expect_symbol("(")?;
+        let mut names = vec![self.identifier(what)?];
+        while self.symbol(&[","]).is_some() {
+            names.push(self.identifier(what)?);
+        }
+        self.expect_symbol(")")?;
+        Ok(names)
+    }
+
+    fn statement(&mut self) -> Result<Statement, PrepareError> {
+        let keyword = match self.peek() {
+            Some(TokenKind::Keyword(keyword)) => *keyword,
+            None if self.error.is_some() => return Err(self.error("a statement")),
+            _ => return Err(PrepareError::UnrecognizedStatement),
+        };
+        match keyword {
+            "select" => self.select().map(Statement::Select),
+            "insert" => self.insert().map(Statement::Insert),
+            "update" => self.update().map(Statement::Update),
+            "delete" => self.delete().map(Statement::Delete),
+            "create" => self.create(),
+            "alter" => self.alter(),
+            "drop" => self.drop(),
+            _ => Err(PrepareError::UnrecognizedStatement),
+        }
+    }
+
+    fn select(&mut self) -> Result<Select, PrepareError> {
+        self.expect_keyword("select")?;
+        if matches!(self.peek(), None | Some(TokenKind::Symbol(";"))) {
+            return Ok(Select {
+                columns: Vec::new(),
+                from: None,
+                filter: None,
+                order_by: None,
+            });
+        }
+        let mut columns = Vec::new();
+        loop {
+            columns.push(self.result_column()?);
+            if self.symbol(&[","]).is_none() {
+                break;
+            }
+        }
+        let from = match self.keyword("from") {
+            true => {
+                let name = self.identifier("a table name")?;
+                Some(if self.symbol(&["("]).is_some() {
+                    let args = self.arguments()?;
+                    TableRef::Function {
+                        name: name.to_ascii_lowercase(),
+                        args,
+                    }
+                } else {
+                    TableRef::Table(name)
+                })
+            }
+            false => None,
+        };
+        let filter = self.filter()?;
+
+        let order_by = if self.keyword("order") {
+            self.expect_keyword("by")?;
+            let expr = self.expression()?;
+            let descending = self.keyword("desc");
+            if !descending {
+                self.keyword("asc");
+            }
+            Some(OrderBy { expr, descending })
+        } else {
+            None
+        };
+        Ok(Select {
+            columns,
+            from,
+            filter,
+            order_by,
+        })
+    }
+
+    /// Reads `*`, `<table>.*` or `<expression> [[as] <alias>]`.
+    fn result_column(&mut self) -> Result<ResultColumn, PrepareError> {
+        if self.symbol(&["*"]).is_some() {
+            return Ok(ResultColumn::All);
+        }
+        if let (
+            Some(TokenKind::Identifier(table)),
+            Some(TokenKind::Symbol(".")),
+            Some(TokenKind::Symbol("*")),
+        ) = (self.peek(), self.peek_at(1), self.peek_at(2))
+        {
+            let table = table.clone();
+            self.position += 3;
+            return Ok(ResultColumn::AllOf(table));
+        }
+        let start = self.offset();
+        let expr = self.expression()?;
+        let sql = self.input[start..self.previous_end()].to_string();
+        let alias = if self.keyword("as") {
+            Some(self.identifier("an alias")?)
+        } else if let Some(TokenKind::Identifier(_)) = self.peek() {
+            Some(self.identifier("an alias")?)
+        } else {
+            None
+        };
+        Ok(ResultColumn::Expr { expr, alias, sql })
+    }
+
+    fn insert(&mut self) -> Result<Insert, PrepareError> {
+        self.expect_keyword("insert")?;
+        let table = match self.keyword("into") {
+            true => Some(self.identifier("a table name")?),
+            false => None,
+        };
+        let columns = match self.peek() {
+            Some(TokenKind::Symbol("(")) => Some(self.identifier_list("a column name")?),
+            _ => None,
+        };
+        if self.keyword("values") {
+            let mut rows = Vec::new();
+            loop {
+                self.expect_symbol("(")?;
+                rows.push(self.arguments()?);
+                if self.symbol(&[","]).is_none() {
+                    break;
+                }
+            }
+            return Ok(Insert {
+                table,
+                columns,
+                values: Values::Rows(rows),
+            });
+        }
+        Ok(Insert {
+            table,
+            columns,
+            values: Values::Words(self.words()),
+        })
+    }
+
+    /// Reads the rest of the statement as blank-separated words, up to a
+    /// `;`. Tokens with nothing between them make up one word, as in
+    /// `person@example.com`, and so does text that is not a token, such as
+    /// `{` or `ü`, up to the next blank.
+    fn words(&mut self) -> Vec<String> {
+        let mut lexer = Lexer::new(self.input);
+        lexer.resume_at(self.offset());
+        let mut words: Vec<String> = Vec::new();
+        let mut previous_end = None;
+        let end_of_words = loop {
+            let (start, end) = match lexer.next() {
+                None => break lexer.offset(),
+                Some(Ok(token)) if token.kind == TokenKind::Symbol(";") => break token.offset,
+                Some(Ok(token)) => (token.offset, token.end),
+                Some(Err(_)) => {
+                    let start = lexer.offset();
+                    let end = self.input[start..]
+                        .find(|c: char| c.is_whitespace() || c == ';')
+                        .map_or(self.input.len(), |length| start + length);
+                    lexer.resume_at(end);
+                    (start, end)
+                }
+            };
+            let text = &self.input[start..end];
+            match words.last_mut() {
+                Some(word) if previous_end == Some(start) => word.push_str(text),
+                _ => words.push(text.to_string()),
+            }
+            previous_end = Some(end);
+        };
+
+        let mut lexer = Lexer::new(self.input);
+        lexer.resume_at(end_of_words);
+        self.read_tokens(lexer);
+        words
+    }
+
+    fn update(&mut self) -> Result<Update, PrepareError> {
+        self.expect_keyword("update")?;
+        let table = self.identifier("a table name")?;
+        self.expect_keyword("set")?;
+        let mut assignments = Vec::new();
+        loop {
+            let column = self.identifier("a column name")?;
+            self.expect_symbol("=")?;
+            assignments.push((column, self.expression()?));
+            if self.symbol(&[","]).is_none() {
+                break;
+            }
+        }
+        let filter = self.filter()?;
+        Ok(Update {
+            table,
+            assignments,
+            filter,
+        })
+    }
+
+    fn delete(&mut self) -> Result<Delete, PrepareError> {
+        self.expect_keyword("delete")?;
+        self.expect_keyword("from")?;
+        let table = self.identifier("a table name")?;
+        let filter = self.filter()?;
+        Ok(Delete { table, filter })
+    }
+
+    /// Reads `[where <expression>]`.
+    fn filter(&mut self) -> Result<Option<Expr>, PrepareError> {
+        if !self.keyword("where") {
+            return Ok(None);
+        }
+        self.expression().map(Some)
+    }
+
+    fn create(&mut self) -> Result<Statement, PrepareError> {
+        self.expect_keyword("create")?;
+        let unique = self.keyword("unique");
+        if self.keyword("index") {
+            let name = self.identifier("an index name")?;
+            self.expect_keyword("on")?;
+            let table = self.identifier("a table name")?;
+            let columns = self.identifier_list("a column name")?;
+            return Ok(Statement::CreateIndex(CreateIndex {
+                name,
+                table,
+                columns,
+                unique,
+            }));
+        }
+        if unique {
+            self.expect_keyword("index")?;
+        }
+        self.expect_keyword("table")?;
+
+        let name = self.identifier("a table name")?;
+        self.expect_symbol("(")?;
+        let mut columns = Vec::new();
+        let mut constraints = Vec::new();
+        loop {
+            match self.peek() {
+                Some(TokenKind::Keyword("constraint" | "unique" | "check" | "foreign")) => {
+                    constraints.push(self.table_constraint()?)
+                }
+                _ => columns.push(self.column_def()?),
+            }
+            if self.symbol(&[","]).is_none() {
+                break;
+            }
+        }
+        self.expect_symbol(")")?;
+        Ok(Statement::CreateTable(CreateTable {
+            name,
+            columns,
+            constraints,
+        }))
+    }
+
+    /// Reads a column definition: `<name> [<type>] [<constraint> ...]`.
+    pub(crate) fn column_def(&mut self) -> Result<ColumnDef, PrepareError> {
+        let start = self.offset();
+        let name = self.identifier("a column name")?;
+
+        // A type is made of names, as in `double precision`, and may end
+        // with a size, as in `varchar(32)`.
+        let type_start = self.offset();
+        let mut type_end = type_start;
+        while let Some(TokenKind::Identifier(_)) = self.peek() {
+            self.position += 1;
+            type_end = self.previous_end();
+        }
+        if type_end > type_start && self.symbol(&["("]).is_some() {
+            loop {
+                self.symbol(&["-", "+"]);
+                match self.next() {
+                    Some(TokenKind::Number(_)) => {}
+                    _ => {
+                        self.position -= 1;
+                        return Err(self.error("a size"));
+                    }
+                }
+                if self.symbol(&[","]).is_none() {
+                    break;
+                }
+            }
+            self.expect_symbol(")")?;
+            type_end = self.previous_end();
+        }
+        let declared_type = self.input[type_start..type_end].to_string();
+
+        let mut constraints = Vec::new();
+        loop {
+            let name = match self.keyword("constraint") {
+                true => Some(self.identifier("a constraint name")?),
+                false => None,
+            };
+            let constraint = if self.keyword("not") {
+                self.expect_keyword("null")?;
+                ColumnConstraint::NotNull
+            } else if self.keyword("null") {
+                ColumnConstraint::Null
+            } else if self.keyword("unique") {
+                ColumnConstraint::Unique
+            } else if self.keyword("primary") {
+                self.expect_word("key")?;
+                ColumnConstraint::PrimaryKey {
+                    autoincrement: self.keyword("autoincrement"),
+                }
+            } else if self.keyword("default") {
+                ColumnConstraint::Default(self.default_value()?)
+            } else if self.keyword("collate") {
+                let collation = self.identifier("a collation name")?;
+                ColumnConstraint::Collate(collation.to_ascii_lowercase())
+            } else if self.keyword("check") {
+                ColumnConstraint::Check(self.check(name)?)
+            } else if self.keyword("references") {
+                ColumnConstraint::References(self.references()?)
+            } else if name.is_some() {
+                return Err(self.error("a column constraint"));
+            } else {
+                break;
+            };
+            constraints.push(constraint);
+        }
+
+        Ok(ColumnDef {
+            name,
+            declared_type,
+            constraints,
+            sql: self.input[start..self.previous_end()].to_string(),
+        })
+    }
+
+    /// Reads what follows `default`: a parenthesized expression, a keyword
+    /// such as `current_timestamp`, or a literal. Unquoted words are text.
+    fn default_value(&mut self) -> Result<Expr, PrepareError> {
+        let start = self.offset();
+        match self.next() {
+            Some(TokenKind::Symbol("(")) => {
+                let expr = self.expression()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Some(TokenKind::Symbol("-" | "+"))
+                if matches!(self.peek(), Some(TokenKind::Number(_))) =>
+            {
+                self.position += 1;
+                let literal = &self.input[start..self.previous_end()];
+                Ok(Expr::Literal(Value::parse_literal(literal)))
+            }
+            Some(TokenKind::Number(value)) => Ok(Expr::Literal(value)),
+            Some(TokenKind::String(text)) => Ok(Expr::Literal(Value::Text(text))),
+            Some(TokenKind::Blob(bytes)) => Ok(Expr::Literal(Value::Blob(bytes))),
+            Some(TokenKind::Identifier(word)) => Ok(Expr::Literal(Value::Text(word))),
+            Some(TokenKind::Keyword("null")) => Ok(Expr::Literal(Value::Null)),
+            Some(TokenKind::Keyword(keyword)) if keyword.starts_with("current_") => {
+                Ok(current_time(keyword))
+            }
+            _ => {
+                self.position -= 1;
+                Err(self.error("a default value"))
+            }
+        }
+    }
+
+    /// Reads what follows `check`: `(<expression>)`.
+    fn check(&mut self, name: Option<String>) -> Result<Check, PrepareError> {
+        self.expect_symbol("(")?;
+        let start = self.offset();
+        let expr = self.expression()?;
+        let sql = self.input[start..self.previous_end()].to_string();
+        self.expect_symbol(")")?;
+        Ok(Check { name, expr, sql })
+    }
+
+    /// Reads what follows `references`.
+    fn references(&mut self) -> Result<References, PrepareError> {
+        let table = self.identifier("a table name")?;
+        let column = match self.symbol(&["("]) {
+            Some(_) => {
+                let column = self.identifier("a column name")?;
+                self.expect_symbol(")")?;
+                Some(column)
+            }
+            None => None,
+        };
+        let mut references = References {
+            table,
+            column,
+            on_delete: OnDelete::NoAction,
+            deferred: false,
+        };
+        loop {
+            if self.keyword("on") {
+                self.expect_keyword("delete")?;
+                references.on_delete = if self.keyword("cascade") {
+                    OnDelete::Cascade
+                } else if self.keyword("restrict") {
+                    OnDelete::Restrict
+                } else if self.keyword("set") {
+                    self.expect_keyword("null")?;
+                    OnDelete::SetNull
+                } else if self.word("no") {
+                    self.expect_word("action")?;
+                    OnDelete::NoAction
+                } else {
+                    return Err(self.error("'cascade', 'restrict', 'set null' or 'no action'"));
+                };
+            } else if self.word("deferrable") {
+                if self.word("initially") {
+                    references.deferred = if self.word("deferred") {
+                        true
+                    } else if self.word("immediate") {
+                        false
+                    } else {
+                        return Err(self.error("'deferred' or 'immediate'"));
+                    };
+                }
+            } else if matches!(self.peek(), Some(TokenKind::Keyword("not")))
+                && matches!(self.peek_at(1), Some(TokenKind::Identifier(word)) if word.eq_ignore_ascii_case("deferrable"))
+            {
+                self.position += 2;
+                references.deferred = false;
+            } else {
+                return Ok(references);
+            }
+        }
+    }
+
+    /// Reads `[constraint <name>]` followed by `unique (<column>, ...)`,
+    /// `check (<expression>)` or `foreign key (<column>) references ...`.
+    fn table_constraint(&mut self) -> Result<TableConstraint, PrepareError> {
+        let name = match self.keyword("constraint") {
+            true => Some(self.identifier("a constraint name")?),
+            false => None,
+        };
+        if self.keyword("unique") {
+            Ok(TableConstraint::Unique(
+                self.identifier_list("a column name")?,
+            ))
+        } else if self.keyword("check") {
+            Ok(TableConstraint::Check(self.check(name)?))
+        } else if self.keyword("foreign") {
+            self.expect_word("key")?;
+            self.expect_symbol("(")?;
+            let column = self.identifier("a column name")?;
+            self.expect_symbol(")")?;
+            self.expect_keyword("references")?;
+            Ok(TableConstraint::ForeignKey {
+                column,
+                references: self.references()?,
+            })
+        } else {
+            Err(self.error("'unique', 'check' or 'foreign key'"))
+        }
+    }
+
+    /// Reads `alter table <table>` followed by `add [column] <definition>`,
+    /// `drop [column] <column>`, `rename [column] <column> to <name>` or
+    /// `rename to <name>`.
+    fn alter(&mut self) -> Result<Statement, PrepareError> {
+        self.expect_keyword("alter")?;
+        self.expect_keyword("table")?;
+        let table = self.identifier("a table name")?;
+        let alteration = if self.keyword("add") {
+            self.keyword("column");
+            Alteration::AddColumn(self.column_def()?)
+        } else if self.keyword("drop") {
+            self.keyword("column");
+            Alteration::DropColumn(self.identifier("a column name")?)
+        } else if self.keyword("rename") {
+            if self.keyword("to") {
+                Alteration::RenameTo(self.identifier("a table name")?)
+            } else {
+                self.keyword("column");
+                let from = self.identifier("a column name")?;
+                self.expect_keyword("to")?;
+                let to = self.identifier("a column name")?;
+                Alteration::RenameColumn { from, to }
+            }
+        } else {
+            return Err(self.error("'add', 'drop' or 'rename'"));
+        };
+        Ok(Statement::AlterTable { table, alteration })
+    }
+
+    fn drop(&mut self) -> Result<Statement, PrepareError> {
+        self.expect_keyword("drop")?;
+        if self.keyword("table") {
+            Ok(Statement::DropTable(self.identifier("a table name")?))
+        } else if self.keyword("index") {
+            Ok(Statement::DropIndex(self.identifier("an index name")?))
+        } else {
+            Err(self.error("'table' or 'index'"))
+        }
+    }
+
+    /// Reads an expression spanning the whole input.
+    pub(crate) fn parse_expression(mut self) -> Result<Expr, PrepareError> {
+        let expr = self.expression()?;
+        self.expect_end("an operator")?;
+        Ok(expr)
+    }
+
+    fn expression(&mut self) -> Result<Expr, PrepareError> {
+        let mut left = self.and()?;
+        while self.keyword("or") {
+            left = binary_expr(BinaryOp::Or, left, self.and()?);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, PrepareError> {
+        let mut left = self.not()?;
+        while self.keyword("and") {
+            left = binary_expr(BinaryOp::And, left, self.not()?);
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, PrepareError> {
+        if self.keyword("not") {
+            return Ok(unary_expr(UnaryOp::Not, self.not()?));
+        }
+        self.equality()
+    }
+
+    fn equality(&mut self) -> Result<Expr, PrepareError> {
+        let mut left = self.comparison()?;
+        loop {
+            if let Some(symbol) = self.symbol(&["=", "==", "!=", "<>"]) {
+                let op = if matches!(symbol, "=" | "==") {
+                    BinaryOp::Eq
+                } else {
+                    BinaryOp::NotEq
+                };
+                left = binary_expr(op, left, self.comparison()?);
+            } else if self.keyword("is") {
+                let op = if self.keyword("not") {
+                    BinaryOp::IsNot
+                } else {
+                    BinaryOp::Is
+                };
+                left = binary_expr(op, left, self.comparison()?);
+            } else if matches!(
+                self.peek(),
+                Some(TokenKind::Keyword("like" | "in" | "between"))
+            ) {
+                left = self.membership(left)?;
+            } else if matches!(self.peek(), Some(TokenKind::Keyword("not"))) {
+                self.position += 1;
+                if !matches!(
+                    self.peek(),
+                    Some(TokenKind::Keyword("like" | "in" | "between"))
+                ) {
+                    return Err(self.error("'like', 'in' or 'between'"));
+                }
+                left = unary_expr(UnaryOp::Not, self.membership(left)?);
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    /// Reads the `like <pattern>`, `in (<expression>, ...)` or
+    /// `between <low> and <high>` that follows `operand`.
+    fn membership(&mut self, operand: Expr) -> Result<Expr, PrepareError> {
+        match self.next() {
+            Some(TokenKind::Keyword("like")) => {
+                Ok(binary_expr(BinaryOp::Like, operand, self.comparison()?))
+            }
+            Some(TokenKind::Keyword("in")) => {
+                self.expect_symbol("(")?;
+                let list = self.arguments()?;
+                Ok(Expr::In {
+                    operand: Box::new(operand),
+                    list,
+                })
+            }
+            _ => {
+                let low = self.comparison()?;
+                self.expect_keyword("and")?;
+                let high = self.comparison()?;
+                Ok(Expr::Between {
+                    operand: Box::new(operand),
+                    low: Box::new(low),
+                    high: Box::new(high),
+                })
+            }
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, PrepareError> {
+        let mut left = self.additive()?;
+        while let Some(symbol) = self.symbol(&["<", "<=", ">", ">="]) {
+            let op = match symbol {
+                "<" => BinaryOp::Lt,
+                "<=" => BinaryOp::LtEq,
+                ">" => BinaryOp::Gt,
+                _ => BinaryOp::GtEq,
+            };
+            left = binary_expr(op, left, self.additive()?);
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Expr, PrepareError> {
+        let mut left = self.multiplicative()?;
+        while let Some(symbol) = self.symbol(&["+", "-"]) {
+            let op = if symbol == "+" {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            left = binary_expr(op, left, self.multiplicative()?);
+        }
+        Ok(left)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, PrepareError> {
+        let mut left = self.concat()?;
+        while let Some(symbol) = self.symbol(&["*", "/", "%"]) {
+            let op = match symbol {
+                "*" => BinaryOp::Mul,
+                "/" => BinaryOp::Div,
+                _ => BinaryOp::Rem,
+            };
+            left = binary_expr(op, left, self.concat()?);
+        }
+        Ok(left)
+    }
+
+    fn concat(&mut self) -> Result<Expr, PrepareError> {
+        let mut left = self.unary()?;
+        while let Some(symbol) = self.symbol(&["||", "->", "->>"]) {
+            let op = match symbol {
+                "||" => BinaryOp::Concat,
+                "->" => BinaryOp::JsonExtract,
+                _ => BinaryOp::JsonExtractValue,
+            };
+            left = binary_expr(op, left, self.unary()?);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, PrepareError> {
+        match self.symbol(&["-", "+"]) {
+            Some("-") => Ok(unary_expr(UnaryOp::Negate, self.unary()?)),
+            Some(_) => Ok(unary_expr(UnaryOp::Plus, self.unary()?)),
+            None => self.collate(),
+        }
+    }
+
+    fn collate(&mut self) -> Result<Expr, PrepareError> {
+        let mut operand = self.primary()?;
+        while self.keyword("collate") {
+            let collation = self.identifier("a collation name")?;
+            operand = Expr::Collate {
+                operand: Box::new(operand),
+                collation: collation.to_ascii_lowercase(),
+            };
+        }
+        Ok(operand)
+    }
+
+    fn primary(&mut self) -> Result<Expr, PrepareError> {
+        match self.next() {
+            Some(TokenKind::Number(value)) => Ok(Expr::Literal(value)),
+            Some(TokenKind::String(text)) => Ok(Expr::Literal(Value::Text(text))),
+            Some(TokenKind::Blob(bytes)) => Ok(Expr::Literal(Value::Blob(bytes))),
+            Some(TokenKind::Keyword("null")) => Ok(Expr::Literal(Value::Null)),
+            Some(TokenKind::Keyword(keyword)) if keyword.starts_with("current_") => {
+                Ok(current_time(keyword))
+            }
+            Some(TokenKind::Identifier(name)) if self.symbol(&["("]).is_some() => {
+                let name = name.to_ascii_lowercase();
+                let args = self.arguments()?;
+                match FUNCTIONS.iter().find(|(function, _)| *function == name) {
+                    Some((_, arity)) if arity.contains(&args.len()) => {
+                        Ok(Expr::Function { name, args })
+                    }
+                    Some((_, arity)) if arity.start() == arity.end() => Err(PrepareError::Invalid(
+                        format!("Function {} takes {} argument(s)", name, arity.start()),
+                    )),
+                    Some((_, arity)) => Err(PrepareError::Invalid(format!(
+                        "Function {} takes at least {} argument(s)",
+                        name,
+                        arity.start()
+                    ))),
+                    None => Err(PrepareError::Invalid(format!("Unknown function {}", name))),
+                }
+            }
+            Some(TokenKind::Identifier(name)) => Ok(Expr::Column(name)),
+            Some(TokenKind::Symbol("(")) => {
+                let expr = self.expression()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            _ => {
+                self.position -= 1;
+                Err(self.error("an expression"))
+            }
+        }
+    }
+
+    /// Reads the arguments of a call, or the values of a row, up to the
+    /// closing parenthesis.
+    fn arguments(&mut self) -> Result<Vec<Expr>, PrepareError> {
+        let mut args = Vec::new();
+        if self.symbol(&[")"]).is_none() {
+            loop {
+                args.push(self.expression()?);
+                if self.symbol(&[","]).is_none() {
+                    break;
+                }
+            }
+            self.expect_symbol(")")?;
+        }
+        Ok(args)
+    }
+}
+
+/// The call a `current_date`, `current_time` or `current_timestamp` keyword
+/// stands for.
+fn current_time(keyword: &str) -> Expr {
+    let (_, name) = CURRENT_TIME_KEYWORDS
+        .iter()
+        .find(|(current, _)| *current == keyword)
+        .expect("Every current_ keyword reads the time");
+    Expr::Function {
+        name: name.to_string(),
+        args: Vec::new(),
+    }
+}
+
+fn unary_expr(op: UnaryOp, operand: Expr) -> Expr {
+    Expr::Unary {
+        op,
+        operand: Box::new(operand),
+    }
+}
+
+fn binary_expr(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::Binary {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
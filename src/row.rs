@@ -0,0 +1,200 @@
+use std::fmt;
+
+use crate::{Column, Schema, Value};
+
+/// Represents a single row in a database table, with one value per column
+/// of the table's schema. Rows are stored as variable-length records: a
+/// header listing the serial type of every value, followed by the values
+/// themselves, so each row only takes the space its values need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub values: Vec<Value>,
+}
+
+/// Serial types of a record header. Integers are stored big-endian in the
+/// fewest bytes that hold them; text and blobs encode their length in the
+/// serial type itself.
+const SERIAL_NULL: u64 = 0;
+const SERIAL_INTEGER_SIZES: [usize; 6] = [1, 2, 3, 4, 6, 8];
+const SERIAL_REAL: u64 = 7;
+const SERIAL_ZERO: u64 = 8;
+const SERIAL_ONE: u64 = 9;
+const SERIAL_BLOB: u64 = 12;
+const SERIAL_TEXT: u64 = 13;
+
+impl Row {
+    /// Serializes a `Row` into a record for writing to disk.
+    pub fn serialize(&self) -> Vec<u8> {
+        encode_record(&self.values)
+    }
+
+    /// Deserializes a record into a `Row`. Columns missing from the end of
+    /// the record, added to the table after the row was written, read back
+    /// as their default value.
+    pub fn deserialize(schema: &Schema, source: &[u8]) -> Row {
+        let (mut values, _) = decode_record(source);
+        values.truncate(schema.columns.len());
+        let stored = values.len();
+        values.extend(schema.columns[stored..].iter().map(Column::default_value));
+        Row { values }
+    }
+}
+
+/// Encodes values as a record: a header holding its own size and the serial
+/// type of every value, followed by the values.
+pub(crate) fn encode_record(values: &[Value]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for value in values {
+        let serial_type = match value {
+            Value::Null => SERIAL_NULL,
+            Value::Integer(0) => SERIAL_ZERO,
+            Value::Integer(1) => SERIAL_ONE,
+            Value::Integer(value) => {
+                let (index, size) = SERIAL_INTEGER_SIZES
+                    .iter()
+                    .enumerate()
+                    .find(|(_, size)| fits_in(*value, **size))
+                    .expect("Every integer fits in 8 bytes");
+                body.extend_from_slice(&value.to_be_bytes()[8 - size..]);
+                index as u64 + 1
+            }
+            Value::Real(value) => {
+                body.extend_from_slice(&value.to_bits().to_be_bytes());
+                SERIAL_REAL
+            }
+            Value::Text(value) => {
+                body.extend_from_slice(value.as_bytes());
+                SERIAL_TEXT + 2 * value.len() as u64
+            }
+            Value::Blob(value) => {
+                body.extend_from_slice(value);
+                SERIAL_BLOB + 2 * value.len() as u64
+            }
+        };
+        write_varint(&mut types, serial_type);
+    }
+
+    // The header starts with its own size, which counts the bytes of the
+    // size itself.
+    let mut header_size = types.len() + 1;
+    while varint_size(header_size as u64) + types.len() > header_size {
+        header_size += 1;
+    }
+    let mut record = Vec::with_capacity(header_size + body.len());
+    write_varint(&mut record, header_size as u64);
+    record.extend_from_slice(&types);
+    record.extend_from_slice(&body);
+    record
+}
+
+/// Decodes the record at the start of `source`, returning its values and
+/// the number of bytes it took.
+pub(crate) fn decode_record(source: &[u8]) -> (Vec<Value>, usize) {
+    let (header_size, mut header_offset) = read_varint(source);
+    let header_size = header_size as usize;
+    let mut body_offset = header_size;
+
+    let mut values = Vec::new();
+    while header_offset < header_size {
+        let (serial_type, read) = read_varint(&source[header_offset..]);
+        header_offset += read;
+
+        let size = serial_type_size(serial_type);
+        let data = &source[body_offset..body_offset + size];
+        body_offset += size;
+        values.push(match serial_type {
+            SERIAL_NULL => Value::Null,
+            1..=6 => {
+                // Sign-extend the big-endian integer to 8 bytes.
+                let fill = if data[0] & 0x80 != 0 { 0xff } else { 0 };
+                let mut bytes = [fill; 8];
+                bytes[8 - size..].copy_from_slice(data);
+                Value::Integer(i64::from_be_bytes(bytes))
+            }
+            SERIAL_REAL => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(data);
+                Value::Real(f64::from_bits(u64::from_be_bytes(bytes)))
+            }
+            SERIAL_ZERO => Value::Integer(0),
+            SERIAL_ONE => Value::Integer(1),
+            n if n >= SERIAL_BLOB && n % 2 == 0 => Value::Blob(data.to_vec()),
+            n if n >= SERIAL_TEXT => match String::from_utf8(data.to_vec()) {
+                Ok(text) => Value::Text(text),
+                Err(_) => panic!("Corrupt record: text is not valid UTF-8"),
+            },
+            n => panic!("Corrupt record: unknown serial type {}", n),
+        });
+    }
+
+    (values, body_offset)
+}
+
+/// Returns `true` if `value` can be stored in `size` bytes.
+fn fits_in(value: i64, size: usize) -> bool {
+    let bits = 8 * size as u32;
+    bits == 64 || (-(1i64 << (bits - 1))..1i64 << (bits - 1)).contains(&value)
+}
+
+/// Number of bytes of data a value of `serial_type` takes in the record body.
+fn serial_type_size(serial_type: u64) -> usize {
+    match serial_type {
+        1..=6 => SERIAL_INTEGER_SIZES[serial_type as usize - 1],
+        SERIAL_REAL => 8,
+        n if n >= SERIAL_BLOB => ((n - SERIAL_BLOB) / 2) as usize,
+        _ => 0,
+    }
+}
+
+/// Number of bytes `value` takes once encoded as a varint.
+const fn varint_size(value: u64) -> usize {
+    let mut size = 1;
+    while size < 9 && value >> (7 * size) != 0 {
+        size += 1;
+    }
+    size
+}
+
+/// Appends `value` as a big-endian varint: seven bits per byte with the high
+/// bit set on every byte but the last. A ninth byte, if needed, carries a
+/// full eight bits.
+fn write_varint(destination: &mut Vec<u8>, value: u64) {
+    let size = varint_size(value);
+    if size == 9 {
+        for i in 0..8 {
+            destination.push((value >> (8 + 7 * (7 - i))) as u8 & 0x7f | 0x80);
+        }
+        destination.push(value as u8);
+        return;
+    }
+    for i in (0..size).rev() {
+        let byte = (value >> (7 * i)) as u8 & 0x7f;
+        destination.push(if i == 0 { byte } else { byte | 0x80 });
+    }
+}
+
+/// Reads a varint, returning its value and the number of bytes it took.
+fn read_varint(source: &[u8]) -> (u64, usize) {
+    let mut value = 0u64;
+    for (i, byte) in source.iter().take(8).enumerate() {
+        value = (value << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return (value, i + 1);
+        }
+    }
+    ((value << 8) | u64::from(source[8]), 9)
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, value) in self.values.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        write!(f, ")")
+    }
+}
@@ -0,0 +1,719 @@
+use std::ops::Range;
+
+use crate::ast::{
+    ColumnConstraint, ColumnDef, CreateIndex, CreateTable, References, Statement, TableConstraint,
+};
+use crate::expression::truth;
+use crate::parser::parse;
+use crate::token::{Token, TokenKind, quote_identifier, tokenize};
+use crate::{Affinity, Collation, Expr, PrepareError, Row, Value};
+
+/// A named column of a table. Its declared type only sets the column's
+/// affinity and, through an optional `(n)` suffix as in `varchar(32)`, the
+/// maximum length of the text and blobs it holds: text is measured in
+/// characters and blobs in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub declared_type: String,
+    pub affinity: Affinity,
+    pub size: Option<usize>,
+    /// Set by a `NOT NULL` constraint.
+    pub not_null: bool,
+    /// Set by a `PRIMARY KEY` constraint.
+    pub primary_key: bool,
+    /// Set by `PRIMARY KEY AUTOINCREMENT`: keys are never reused, even
+    /// once the rows holding the largest ones are deleted.
+    pub autoincrement: bool,
+    /// Set by a `UNIQUE` constraint.
+    pub unique: bool,
+    /// Name of the collation set by a `COLLATE` constraint, which compares
+    /// the column's text and orders it in indexes. `BINARY` if unset.
+    pub collation: Option<String>,
+    /// Expression whose value is stored when an insert omits the column,
+    /// NULL if unset. It reads no columns, but may read the clock.
+    pub default: Option<Expr>,
+}
+
+impl Column {
+    /// Maximum length of text and blobs in columns declared without a size.
+    pub const DEFAULT_TEXT_SIZE: usize = 255;
+
+    fn new(name: &str, declared_type: String) -> Result<Column, PrepareError> {
+        let size = match declared_type.split_once('(') {
+            Some((_, arguments)) => {
+                let size = arguments
+                    .split([',', ')'])
+                    .next()
+                    .and_then(|size| size.trim().parse::<usize>().ok())
+                    .filter(|size| *size > 0)
+                    .ok_or_else(|| {
+                        PrepareError::Invalid(format!("Invalid column type '{}'", declared_type))
+                    })?;
+                Some(size)
+            }
+            None => None,
+        };
+
+        Ok(Column {
+            name: name.to_string(),
+            affinity: Affinity::from_declared_type(&declared_type),
+            declared_type,
+            size,
+            not_null: false,
+            primary_key: false,
+            autoincrement: false,
+            unique: false,
+            collation: None,
+            default: None,
+        })
+    }
+
+    /// Builds a column from its definition, returning it with its `CHECK`
+    /// constraints and its `REFERENCES` constraint.
+    fn define(definition: &ColumnDef) -> Result<ParsedColumn, PrepareError> {
+        let name = &definition.name;
+        let mut column = Column::new(name, definition.declared_type.clone())?;
+        let mut checks = Vec::new();
+        let mut foreign_key = None;
+        for constraint in &definition.constraints {
+            match constraint {
+                ColumnConstraint::NotNull => column.not_null = true,
+                ColumnConstraint::Null => column.not_null = false,
+                ColumnConstraint::Unique => column.unique = true,
+                ColumnConstraint::PrimaryKey { autoincrement } => {
+                    column.primary_key = true;
+                    column.autoincrement = *autoincrement;
+                }
+                ColumnConstraint::Default(default) => {
+                    if !default.columns().is_empty() {
+                        return Err(PrepareError::Invalid(format!(
+                            "Invalid constraint 'default' on column {}",
+                            name
+                        )));
+                    }
+                    column.default = Some(default.clone());
+                }
+                ColumnConstraint::Collate(collation) => column.collation = Some(collation.clone()),
+                ColumnConstraint::Check(check) => checks.push(check.clone()),
+                ColumnConstraint::References(references) => {
+                    foreign_key = Some(ForeignKey::referencing(references))
+                }
+            }
+        }
+        Ok((column, checks, foreign_key))
+    }
+
+    /// Value stored when an insert omits the column.
+    pub fn default_value(&self) -> Value {
+        self.default.as_ref().map_or(Value::Null, |default| {
+            self.affinity
+                .apply(default.evaluate(&[], &Row { values: Vec::new() }))
+        })
+    }
+
+    /// Returns the collation comparing the column's values.
+    pub fn collation(&self) -> Collation {
+        Collation::named(self.collation.as_deref())
+    }
+
+    /// Returns `true` for columns declared `JSON`, whose values must be
+    /// well-formed JSON.
+    pub fn is_json(&self) -> bool {
+        self.declared_type
+            .split('(')
+            .next()
+            .is_some_and(|name| name.trim().eq_ignore_ascii_case("json"))
+    }
+
+    /// Maximum length of a text value in characters, or of a blob in bytes.
+    pub fn max_length(&self) -> usize {
+        self.size.unwrap_or(Self::DEFAULT_TEXT_SIZE)
+    }
+}
+
+/// A column definition along with the constraints it declares on the table.
+type ParsedColumn = (Column, Vec<Check>, Option<ForeignKey>);
+
+/// What happens to the rows referencing a parent row when it is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnDelete {
+    /// The delete fails if rows still reference the parent once the
+    /// constraint is checked.
+    #[default]
+    NoAction,
+    /// The delete fails straight away, even if the constraint is deferred.
+    Restrict,
+    /// The referencing rows are deleted too.
+    Cascade,
+    /// The referencing column is set to NULL.
+    SetNull,
+}
+
+/// A `REFERENCES` constraint: every non-NULL value of `column` must be a
+/// value of `parent_column` in the `parent` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKey {
+    pub column: usize,
+    pub parent: String,
+    /// The referenced column, the parent's primary key if unset.
+    pub parent_column: Option<String>,
+    pub on_delete: OnDelete,
+    /// Set by `DEFERRABLE INITIALLY DEFERRED`: the constraint is checked
+    /// when the statement commits rather than after each change. Every
+    /// statement commits on its own, so this lets a single delete remove
+    /// rows that reference each other.
+    pub deferred: bool,
+}
+
+impl ForeignKey {
+    /// Builds the constraint a `REFERENCES` clause declares. The
+    /// constrained column is resolved once the whole table is known.
+    fn referencing(references: &References) -> ForeignKey {
+        ForeignKey {
+            column: 0,
+            parent: references.table.clone(),
+            parent_column: references.column.clone(),
+            on_delete: references.on_delete,
+            deferred: references.deferred,
+        }
+    }
+}
+
+/// A `CHECK` constraint: an expression that no row may make false.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: Option<String>,
+    pub expr: Expr,
+    /// The expression as written, which names the constraint if it has no
+    /// name of its own.
+    pub sql: String,
+}
+
+impl Check {
+    /// Returns `true` unless the row makes the expression false. A NULL
+    /// result satisfies the constraint.
+    pub fn is_satisfied(&self, columns: &[Column], row: &Row) -> bool {
+        truth(&self.expr.evaluate(columns, row)) != Some(false)
+    }
+
+    /// The name violations are reported with.
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.sql)
+    }
+}
+
+/// The definition of a table: its name, its columns in storage order, its
+/// table constraints and the `create table` statement it was built from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub name: String,
+    pub columns: Vec<Column>,
+    /// Column sets whose values must be unique, from `UNIQUE` column and
+    /// table constraints in the order they appear.
+    pub unique: Vec<Vec<usize>>,
+    /// `CHECK` constraints of the columns and of the table.
+    pub checks: Vec<Check>,
+    /// `REFERENCES` constraints of the columns and `FOREIGN KEY` table
+    /// constraints.
+    pub foreign_keys: Vec<ForeignKey>,
+    pub sql: String,
+}
+
+impl Schema {
+    /// Name of the tutorial's users table.
+    pub const USERS_NAME: &str = "users";
+    /// The statement describing the tutorial's users table, which every new
+    /// database starts with.
+    pub const DEFAULT_SQL: &str =
+        "create table users (id integer, username text(32), email text(255))";
+
+    /// Returns the schema of the tutorial's users table.
+    pub fn users() -> Schema {
+        Schema::parse(Self::DEFAULT_SQL).expect("The default schema must be valid")
+    }
+
+    /// Parses a `create table <name> (<column> <type> <constraints>, ...)`
+    /// statement. Table constraints such as `unique (<column>, ...)` may
+    /// follow the columns.
+    pub fn parse(sql: &str) -> Result<Schema, PrepareError> {
+        match parse(sql)? {
+            Statement::CreateTable(definition) => Schema::new(&definition, sql),
+            _ => Err(PrepareError::Invalid(
+                "Expected 'create table <name> (<column> [<type>], ...)'".to_string(),
+            )),
+        }
+    }
+
+    /// Builds the schema a parsed `create table` statement describes, `sql`
+    /// being the statement as written.
+    pub(crate) fn new(definition: &CreateTable, sql: &str) -> Result<Schema, PrepareError> {
+        let name = &definition.name;
+        let mut columns: Vec<Column> = Vec::new();
+        let mut unique: Vec<Vec<String>> = Vec::new();
+        let mut checks: Vec<Check> = Vec::new();
+        let mut foreign_keys: Vec<(String, ForeignKey)> = Vec::new();
+        for column_definition in &definition.columns {
+            let (column, column_checks, foreign_key) = Column::define(column_definition)?;
+            if let Some(foreign_key) = foreign_key {
+                foreign_keys.push((column.name.clone(), foreign_key));
+            }
+            if column.unique {
+                unique.push(vec![column.name.clone()]);
+            }
+            if columns.iter().any(|c| c.name == column.name) {
+                return Err(PrepareError::Invalid(format!(
+                    "Duplicate column name '{}'",
+                    column.name
+                )));
+            }
+            columns.push(column);
+            checks.extend(column_checks);
+        }
+        for constraint in &definition.constraints {
+            match constraint {
+                TableConstraint::Unique(names) => unique.push(names.clone()),
+                TableConstraint::Check(check) => checks.push(check.clone()),
+                TableConstraint::ForeignKey { column, references } => {
+                    foreign_keys.push((column.clone(), ForeignKey::referencing(references)))
+                }
+            }
+        }
+
+        let unknown_column = checks
+            .iter()
+            .flat_map(|check| check.expr.columns())
+            .find(|name| !columns.iter().any(|c| c.name == *name));
+        if let Some(name) = unknown_column {
+            return Err(PrepareError::Invalid(format!("Unknown column '{}'", name)));
+        }
+
+        let mut primary_keys = columns.iter().filter(|c| c.primary_key);
+        if let Some(column) = primary_keys.next() {
+            if primary_keys.next().is_some() {
+                return Err(PrepareError::Invalid(format!(
+                    "Table {} has more than one primary key",
+                    name
+                )));
+            }
+            // Only an integer primary key can be the row's key. Any other
+            // is kept unique through an index, like a `UNIQUE` column.
+            let key = vec![column.name.clone()];
+            if column.affinity != Affinity::Integer && !unique.contains(&key) {
+                unique.push(key);
+            }
+        }
+
+        let position = |name: &str| {
+            columns
+                .iter()
+                .position(|c| c.name == name)
+                .ok_or(PrepareError::Invalid(format!("Unknown column '{}'", name)))
+        };
+        let unique = unique
+            .iter()
+            .map(|names| names.iter().map(|name| position(name)).collect())
+            .collect::<Result<Vec<_>, _>>()?;
+        let foreign_keys = foreign_keys
+            .into_iter()
+            .map(|(name, foreign_key)| {
+                Ok(ForeignKey {
+                    column: position(&name)?,
+                    ..foreign_key
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Schema {
+            name: name.to_string(),
+            columns,
+            unique,
+            checks,
+            foreign_keys,
+            sql: sql.trim().to_string(),
+        })
+    }
+
+    /// Index of the column whose value is the row's key in the table's
+    /// B-tree: the integer column declared `PRIMARY KEY`. The tutorial's
+    /// users table declares none and is keyed by its `id` column.
+    pub fn primary_key(&self) -> Option<usize> {
+        match self.declared_primary_key() {
+            Some(index) => Some(index).filter(|&i| self.columns[i].affinity == Affinity::Integer),
+            None if self.name == Self::USERS_NAME => self
+                .columns
+                .iter()
+                .position(|c| c.name == "id" && c.affinity == Affinity::Integer),
+            None => None,
+        }
+    }
+
+    /// Index of the column declared `PRIMARY KEY`, whatever its type.
+    fn declared_primary_key(&self) -> Option<usize> {
+        self.columns.iter().position(|c| c.primary_key)
+    }
+
+    /// Returns the schema as changed by an `ALTER TABLE` statement on this
+    /// table. The statement stored in the catalog is rewritten accordingly.
+    pub fn alter(&self, alteration: &Alteration) -> Result<Schema, PrepareError> {
+        let column_error = |action: &str, name: &str| {
+            PrepareError::Invalid(format!("Cannot {} column {}", action, name))
+        };
+        let position = |name: &str| {
+            self.columns
+                .iter()
+                .position(|c| c.name == name)
+                .ok_or_else(|| {
+                    PrepareError::Invalid(format!("Table {} has no column '{}'", self.name, name))
+                })
+        };
+
+        match alteration {
+            Alteration::AddColumn(definition) => {
+                let (column, _, _) = Column::define(definition)?;
+                if column.primary_key {
+                    return Err(column_error("add a PRIMARY KEY", &column.name));
+                }
+                if column.unique {
+                    return Err(column_error("add a UNIQUE", &column.name));
+                }
+                // Existing rows read the default whenever they are loaded,
+                // so it must not change over time.
+                if !matches!(column.default, None | Some(Expr::Literal(_))) {
+                    return Err(PrepareError::Invalid(format!(
+                        "Cannot add a column {} with non-constant default",
+                        column.name
+                    )));
+                }
+                if column.not_null && column.default_value() == Value::Null {
+                    return Err(PrepareError::Invalid(format!(
+                        "Cannot add a NOT NULL column {} without a default",
+                        column.name
+                    )));
+                }
+                let table = TableDefinition::new(&self.sql);
+                let last = table.definitions.last().expect("A table has a column");
+                let end = table.span(last).end;
+                let added = format!(", {}", definition.sql);
+                Schema::parse(&replace_spans(&self.sql, vec![(end..end, added)]))
+            }
+            Alteration::DropColumn(name) => {
+                let index = position(name)?;
+                let constrained = self.primary_key() == Some(index)
+                    || self.unique.iter().any(|columns| columns.contains(&index))
+                    || self.foreign_keys.iter().any(|fk| fk.column == index);
+                if constrained || self.columns.len() == 1 {
+                    return Err(column_error("drop", name));
+                }
+                let table = TableDefinition::new(&self.sql);
+                let definitions = &table.definitions;
+                let dropped = definitions
+                    .iter()
+                    .position(|definition| table.defines_column(definition, name))
+                    .expect("The dropped column is defined");
+                // The comma before the definition goes with it, or the one
+                // after it for the first definition.
+                let span = table.span(&definitions[dropped]);
+                let removed = match dropped {
+                    0 => span.start..table.span(&definitions[1]).start,
+                    _ => table.span(&definitions[dropped - 1]).end..span.end,
+                };
+                // Checks of other columns may still use the dropped one.
+                Schema::parse(&replace_spans(&self.sql, vec![(removed, String::new())]))
+                    .map_err(|_| column_error("drop", name))
+            }
+            Alteration::RenameColumn { from, to } => {
+                position(from)?;
+                if to.is_empty() || self.columns.iter().any(|c| c.name == *to) {
+                    return Err(PrepareError::Invalid(format!(
+                        "Invalid column name '{}'",
+                        to
+                    )));
+                }
+                self.rename(&Rename::Column {
+                    table: &self.name,
+                    from,
+                    to,
+                })
+            }
+            Alteration::RenameTo(to) => {
+                if to.is_empty() {
+                    return Err(PrepareError::Invalid(format!(
+                        "Invalid table name '{}'",
+                        to
+                    )));
+                }
+                self.rename(&Rename::Table {
+                    from: &self.name,
+                    to,
+                })
+            }
+        }
+    }
+
+    /// Rewrites the names this table uses for its own columns or for the
+    /// tables and columns its foreign keys reference.
+    pub(crate) fn rename(&self, rename: &Rename) -> Result<Schema, PrepareError> {
+        let table = TableDefinition::new(&self.sql);
+        let mut edits = Vec::new();
+        match rename {
+            Rename::Table { from, to } if self.name == *from => {
+                edits.push((table.name_span(), quote_identifier(to)));
+            }
+            _ => {}
+        }
+        for definition in &table.definitions {
+            edits.extend(renamed_identifiers(
+                &table.tokens[definition.clone()],
+                &self.name,
+                rename,
+            ));
+        }
+        if edits.is_empty() {
+            return Ok(self.clone());
+        }
+        Schema::parse(&replace_spans(&self.sql, edits))
+    }
+
+    /// Returns `true` if the primary key is declared `AUTOINCREMENT`.
+    pub fn is_autoincrement(&self) -> bool {
+        self.columns.iter().any(|c| c.autoincrement)
+    }
+
+    /// Names of the collations used by the columns and `CHECK` constraints.
+    pub fn collations(&self) -> Vec<&str> {
+        self.columns
+            .iter()
+            .filter_map(|column| column.collation.as_deref())
+            .chain(self.checks.iter().flat_map(|check| check.expr.collations()))
+            .collect()
+    }
+
+    /// Index of the column referenced by `foreign_key`, which must point at
+    /// this table.
+    pub fn referenced_column(&self, foreign_key: &ForeignKey) -> Option<usize> {
+        match &foreign_key.parent_column {
+            Some(name) => self.columns.iter().position(|c| c.name == *name),
+            None => self.declared_primary_key().or_else(|| self.primary_key()),
+        }
+    }
+}
+
+/// The definition of an index created by a `create [unique] index`
+/// statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexSchema {
+    pub name: String,
+    pub table: String,
+    /// The indexed columns, in the order their values are sorted by.
+    pub columns: Vec<String>,
+    pub unique: bool,
+    pub sql: String,
+}
+
+impl IndexSchema {
+    /// Parses a `create [unique] index <name> on <table> (<column>, ...)`
+    /// statement.
+    pub fn parse(sql: &str) -> Result<IndexSchema, PrepareError> {
+        match parse(sql)? {
+            Statement::CreateIndex(definition) => Ok(IndexSchema::new(definition, sql)),
+            _ => Err(PrepareError::Invalid(
+                "Expected 'create [unique] index <name> on <table> (<column>, ...)'".to_string(),
+            )),
+        }
+    }
+
+    /// Builds the index a parsed `create index` statement describes, `sql`
+    /// being the statement as written.
+    pub(crate) fn new(definition: CreateIndex, sql: &str) -> IndexSchema {
+        IndexSchema {
+            name: definition.name,
+            table: definition.table,
+            columns: definition.columns,
+            unique: definition.unique,
+            sql: sql.trim().to_string(),
+        }
+    }
+}
+
+/// A change made to a table by an `ALTER TABLE` statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alteration {
+    /// Appends a column, given by its definition.
+    AddColumn(ColumnDef),
+    DropColumn(String),
+    RenameColumn {
+        from: String,
+        to: String,
+    },
+    RenameTo(String),
+}
+
+/// A name changed by `ALTER TABLE`, to be replaced wherever a table
+/// definition uses it.
+pub(crate) enum Rename<'a> {
+    Table {
+        from: &'a str,
+        to: &'a str,
+    },
+    Column {
+        table: &'a str,
+        from: &'a str,
+        to: &'a str,
+    },
+}
+
+/// The tokens of a `create table` statement, with the token ranges of the
+/// column definitions and table constraints between its parentheses.
+struct TableDefinition {
+    tokens: Vec<Token>,
+    definitions: Vec<Range<usize>>,
+}
+
+impl TableDefinition {
+    /// Index of the table name, which follows `create table`.
+    const NAME: usize = 2;
+
+    fn new(sql: &str) -> TableDefinition {
+        let tokens = tokenize(sql).expect("Schema SQL is valid");
+        let mut definitions = Vec::new();
+        let mut depth = 0usize;
+        // The definitions start after the parenthesis that follows the name.
+        let mut start = Self::NAME + 2;
+        for (i, token) in tokens.iter().enumerate().skip(start) {
+            match token.kind {
+                TokenKind::Symbol("(") => depth += 1,
+                TokenKind::Symbol(")") if depth == 0 => {
+                    definitions.push(start..i);
+                    break;
+                }
+                TokenKind::Symbol(")") => depth -= 1,
+                TokenKind::Symbol(",") if depth == 0 => {
+                    definitions.push(start..i);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        TableDefinition {
+            tokens,
+            definitions,
+        }
+    }
+
+    fn name_span(&self) -> Range<usize> {
+        let name = &self.tokens[Self::NAME];
+        name.offset..name.end
+    }
+
+    /// Byte range of the statement a definition spans.
+    fn span(&self, definition: &Range<usize>) -> Range<usize> {
+        self.tokens[definition.start].offset..self.tokens[definition.end - 1].end
+    }
+
+    /// Returns `true` if `definition` is the one of the column `name`.
+    /// Table constraints start with a keyword rather than a name.
+    fn defines_column(&self, definition: &Range<usize>, name: &str) -> bool {
+        matches!(&self.tokens[definition.start].kind, TokenKind::Identifier(column) if column == name)
+    }
+}
+
+/// Returns the edits applying `rename` to the tokens of one definition of
+/// `table`: the column's own name, the columns listed by `unique` and
+/// `foreign key` or used by checks, and the table and column named by
+/// `references`. Strings, defaults and function names are left alone.
+fn renamed_identifiers(
+    tokens: &[Token],
+    table: &str,
+    rename: &Rename,
+) -> Vec<(Range<usize>, String)> {
+    let renamed_column = |name: &str| match rename {
+        Rename::Column { table: t, from, to } if *t == table && name == *from => Some(*to),
+        _ => None,
+    };
+    let kind = |i: usize| tokens.get(i).map(|token| &token.kind);
+    let is_symbol =
+        |i: usize, symbol: &str| matches!(kind(i), Some(TokenKind::Symbol(s)) if *s == symbol);
+    let mut edits = Vec::new();
+    let mut edit = |token: &Token, to: &str| {
+        edits.push((token.offset..token.end, quote_identifier(to)));
+    };
+
+    // A column definition starts with the column's name.
+    if let Some(TokenKind::Identifier(name)) = kind(0)
+        && let Some(to) = renamed_column(name)
+    {
+        edit(&tokens[0], to);
+    }
+    let mut i = 0;
+    while i < tokens.len() {
+        let lists_columns = match &tokens[i].kind {
+            TokenKind::Keyword("check" | "unique") => true,
+            TokenKind::Identifier(word) => word.eq_ignore_ascii_case("key"),
+            _ => false,
+        };
+        if lists_columns && is_symbol(i + 1, "(") {
+            // `check (...)`, `unique (...)` or `foreign key (...)`.
+            let end = closing_parenthesis(tokens, i + 1);
+            for (j, token) in tokens.iter().enumerate().take(end).skip(i + 2) {
+                // Function names and collations are not columns.
+                if let TokenKind::Identifier(name) = &token.kind
+                    && let Some(to) = renamed_column(name)
+                    && !is_symbol(j + 1, "(")
+                    && kind(j - 1) != Some(&TokenKind::Keyword("collate"))
+                {
+                    edit(token, to);
+                }
+            }
+            i = end;
+        } else if tokens[i].kind == TokenKind::Keyword("references")
+            && let Some(TokenKind::Identifier(parent)) = kind(i + 1)
+        {
+            match rename {
+                Rename::Table { from, to } if parent == from => edit(&tokens[i + 1], to),
+                Rename::Column { table, from, to }
+                    if parent == table
+                        && is_symbol(i + 2, "(")
+                        && matches!(kind(i + 3), Some(TokenKind::Identifier(column)) if column == from) =>
+                {
+                    edit(&tokens[i + 3], to)
+                }
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    edits
+}
+
+/// Index of the parenthesis closing the one at `open`.
+fn closing_parenthesis(tokens: &[Token], open: usize) -> usize {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.kind {
+            TokenKind::Symbol("(") => depth += 1,
+            TokenKind::Symbol(")") => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// Replaces byte ranges of `sql`, which must not overlap, with new text.
+fn replace_spans(sql: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(span, _)| span.start);
+    let mut output = String::new();
+    let mut copied = 0;
+    for (span, text) in edits {
+        output.push_str(&sql[copied..span.start]);
+        output.push_str(&text);
+        copied = span.end;
+    }
+    output.push_str(&sql[copied..]);
+    output
+}
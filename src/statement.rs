@@ -0,0 +1,941 @@
+use std::error::Error;
+use std::{fmt, io};
+
+use crate::ast::{self, TableRef};
+use crate::expression::truth;
+use crate::json;
+use crate::{
+    Affinity, Alteration, BinaryOp, Collation, Column, Database, Expr, IndexSchema, Row, Schema,
+    Value, parse,
+};
+
+#[derive(Debug, Clone)]
+pub enum PrepareError {
+    /// The statement does not follow the grammar: `expected` describes what
+    /// should have come at byte `offset` of the input, where `found` was read
+    /// instead, `None` at the end of the input.
+    SyntaxError {
+        expected: String,
+        found: Option<String>,
+        offset: usize,
+    },
+    /// A statement that parses but cannot be prepared as written, such as
+    /// one naming a column its table lacks. Reported as a syntax error.
+    Invalid(String),
+    StringTooLong,
+    UnrecognizedStatement,
+    InvalidId,
+    UnknownTable(String),
+    UnknownIndex(String),
+    UnknownCollation(String),
+    ProtectedTable(String),
+}
+
+#[derive(Debug)]
+pub enum ExecuteError {
+    TableFull,
+    ReadOnly,
+    TableExists(String),
+    IndexExists(String),
+    SchemaTooLarge,
+    RowTooLarge,
+    DuplicateKey,
+    ConstraintViolation(String),
+    /// A value computed while executing, such as by an update's
+    /// assignment, does not fit its column.
+    InvalidValue(PrepareError),
+    Io(io::Error),
+}
+
+impl Error for PrepareError {}
+impl Error for ExecuteError {}
+
+impl fmt::Display for PrepareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrepareError::SyntaxError {
+                expected,
+                found: Some(found),
+                offset,
+            } => write!(
+                f,
+                "Syntax error: Expected {} but found '{}' at offset {}",
+                expected, found, offset
+            ),
+            PrepareError::SyntaxError {
+                expected,
+                found: None,
+                offset,
+            } => write!(
+                f,
+                "Syntax error: Expected {} but found end of input at offset {}",
+                expected, offset
+            ),
+            PrepareError::Invalid(s) => write!(f, "Syntax error: {}", s),
+            PrepareError::StringTooLong => write!(f, "String is too long."),
+            PrepareError::UnrecognizedStatement => write!(f, "Unrecognized statement."),
+            PrepareError::InvalidId => write!(f, "ID must be positive."),
+            PrepareError::UnknownTable(name) => write!(f, "Unknown table {}.", name),
+            PrepareError::UnknownIndex(name) => write!(f, "Unknown index {}.", name),
+            PrepareError::UnknownCollation(name) => write!(f, "Unknown collation {}.", name),
+            PrepareError::ProtectedTable(name) => write!(f, "Table {} may not be modified.", name),
+        }
+    }
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecuteError::TableFull => write!(f, "Error: Table full."),
+            ExecuteError::ReadOnly => write!(f, "Error: Database is read-only."),
+            ExecuteError::TableExists(name) => write!(f, "Error: Table {} already exists.", name),
+            ExecuteError::IndexExists(name) => write!(f, "Error: Index {} already exists.", name),
+            ExecuteError::SchemaTooLarge => write!(f, "Error: Table definition is too large."),
+            ExecuteError::RowTooLarge => write!(f, "Error: Row is too large."),
+            ExecuteError::DuplicateKey => write!(f, "Error: Duplicate key."),
+            ExecuteError::ConstraintViolation(reason) => write!(f, "Error: {}.", reason),
+            ExecuteError::InvalidValue(error) => write!(f, "Error: {}", error),
+            ExecuteError::Io(e) => write!(f, "IO Error: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ExecuteError {
+    fn from(err: io::Error) -> Self {
+        ExecuteError::Io(err)
+    }
+}
+
+/// Table targeted by statements that do not name one, as in the tutorial.
+const DEFAULT_TABLE: &str = Schema::USERS_NAME;
+
+/// Represents a database statement.
+pub enum Statement {
+    CreateTable(Schema),
+    CreateIndex(IndexSchema),
+    Select {
+        source: Source,
+        /// The name and value of each column of the returned rows.
+        columns: Vec<(String, Expr)>,
+        /// Keeps the rows for which the expression is true.
+        filter: Option<Expr>,
+        /// Sorts the rows by an expression, in descending order if set.
+        order_by: Option<(Expr, bool)>,
+    },
+    Insert {
+        table: String,
+        rows: Vec<Row>,
+    },
+    Update {
+        table: String,
+        /// The columns to set and the values to set them to, computed from
+        /// the row before the update.
+        assignments: Vec<(usize, Expr)>,
+        filter: Option<Expr>,
+    },
+    Delete {
+        table: String,
+        filter: Option<Expr>,
+    },
+    AlterTable {
+        table: String,
+        alteration: Alteration,
+    },
+    DropTable {
+        table: String,
+    },
+    DropIndex {
+        index: String,
+    },
+}
+
+/// Where a `select` reads its rows from.
+pub enum Source {
+    Table(String),
+    /// `json_each(<json> [, <path>])`: the elements of a JSON array or the
+    /// members of a JSON object.
+    JsonEach(Vec<Expr>),
+    /// No table: the columns are evaluated once.
+    Nothing,
+}
+
+impl Statement {
+    /// Parses a raw input string into a `Statement`.
+    /// Tables are looked up in `database` and the values of an insert are
+    /// checked against the table's schema.
+    /// Returns a `Result` to handle parsing errors gracefully.
+    pub fn prepare(input: &str, database: &Database) -> Result<Statement, PrepareError> {
+        match parse(input)? {
+            ast::Statement::Select(select) => Self::prepare_select(select, database),
+            ast::Statement::Insert(insert) => Self::prepare_insert(insert, database),
+            ast::Statement::Update(update) => Self::prepare_update(update, database),
+            ast::Statement::Delete(delete) => Self::prepare_delete(delete, database),
+            ast::Statement::CreateTable(definition) => {
+                Self::prepare_create_table(Schema::new(&definition, input)?, database)
+            }
+            ast::Statement::CreateIndex(definition) => {
+                Self::prepare_create_index(IndexSchema::new(definition, input), database)
+            }
+            ast::Statement::AlterTable { table, alteration } => {
+                Self::prepare_alter_table(&table, alteration, database)
+            }
+            ast::Statement::DropTable(table) => Self::prepare_drop_table(&table, database),
+            ast::Statement::DropIndex(index) => Self::prepare_drop_index(&index, database),
+        }
+    }
+
+    /// Checks a `create table` statement.
+    fn prepare_create_table(
+        schema: Schema,
+        database: &Database,
+    ) -> Result<Statement, PrepareError> {
+        check_table_name(&schema.name)?;
+        check_collations(&schema)?;
+        check_foreign_keys(&schema, database)?;
+        Ok(Statement::CreateTable(schema))
+    }
+
+    /// Checks a `create [unique] index` statement on an existing table.
+    fn prepare_create_index(
+        definition: IndexSchema,
+        database: &Database,
+    ) -> Result<Statement, PrepareError> {
+        if definition.name.starts_with(Database::RESERVED_PREFIX) {
+            return Err(PrepareError::Invalid(format!(
+                "Index name {} is reserved for internal use",
+                definition.name
+            )));
+        }
+        let schema = lookup_table(database, &definition.table)?;
+        if schema.name.starts_with(Database::RESERVED_PREFIX) {
+            return Err(PrepareError::ProtectedTable(schema.name.clone()));
+        }
+        if let Some(name) = definition
+            .columns
+            .iter()
+            .find(|name| !schema.columns.iter().any(|column| column.name == **name))
+        {
+            return Err(PrepareError::Invalid(format!(
+                "Table {} has no column '{}'",
+                schema.name, name
+            )));
+        }
+        Ok(Statement::CreateIndex(definition))
+    }
+
+    /// Checks an `alter table` statement against the table it changes.
+    fn prepare_alter_table(
+        table: &str,
+        alteration: Alteration,
+        database: &Database,
+    ) -> Result<Statement, PrepareError> {
+        let schema = lookup_table(database, table)?;
+        if schema.name.starts_with(Database::RESERVED_PREFIX) {
+            return Err(PrepareError::ProtectedTable(schema.name.clone()));
+        }
+
+        let altered = schema.alter(&alteration)?;
+        check_collations(&altered)?;
+        match &alteration {
+            Alteration::AddColumn(_) => check_foreign_keys(&altered, database)?,
+            Alteration::DropColumn(column) => {
+                let position = schema.columns.iter().position(|c| c.name == *column);
+                let referenced = database.tables().iter().any(|child| {
+                    child.schema.foreign_keys.iter().any(|fk| {
+                        fk.parent == schema.name && schema.referenced_column(fk) == position
+                    })
+                });
+                let indexed = database.indexes().iter().any(|index| {
+                    index.table == schema.name && index.columns.iter().any(|&c| Some(c) == position)
+                });
+                if referenced || indexed {
+                    return Err(PrepareError::Invalid(format!(
+                        "Cannot drop column {}",
+                        column
+                    )));
+                }
+            }
+            Alteration::RenameTo(name) => check_table_name(name)?,
+            Alteration::RenameColumn { .. } => {}
+        }
+        Ok(Statement::AlterTable {
+            table: schema.name.clone(),
+            alteration,
+        })
+    }
+
+    /// Checks a `drop table` statement.
+    fn prepare_drop_table(table: &str, database: &Database) -> Result<Statement, PrepareError> {
+        let schema = lookup_table(database, table)?;
+        if schema.name.starts_with(Database::RESERVED_PREFIX) {
+            return Err(PrepareError::ProtectedTable(schema.name.clone()));
+        }
+        Ok(Statement::DropTable {
+            table: schema.name.clone(),
+        })
+    }
+
+    /// Checks a `drop index` statement. The indexes enforcing `UNIQUE`
+    /// constraints go away with their table only.
+    fn prepare_drop_index(name: &str, database: &Database) -> Result<Statement, PrepareError> {
+        let index = database
+            .indexes()
+            .iter()
+            .find(|index| index.name == name)
+            .ok_or_else(|| PrepareError::UnknownIndex(name.to_string()))?;
+        if index.is_automatic() {
+            return Err(PrepareError::Invalid(format!(
+                "Index {} belongs to a UNIQUE constraint of {} and cannot be dropped",
+                index.name, index.table
+            )));
+        }
+        Ok(Statement::DropIndex {
+            index: index.name.clone(),
+        })
+    }
+
+    /// Checks a `select`. `select` alone reads every column of the users
+    /// table, and a `select` of expressions may name no table at all. The
+    /// table may be `json_each(<json> [, <path>])`.
+    fn prepare_select(select: ast::Select, database: &Database) -> Result<Statement, PrepareError> {
+        let (source, schema) = match select.from {
+            None if select.columns.is_empty() => {
+                let schema = lookup_table(database, DEFAULT_TABLE)?;
+                (Source::Table(schema.name.clone()), Some(schema.clone()))
+            }
+            None => (Source::Nothing, None),
+            Some(TableRef::Table(table)) => {
+                let schema = lookup_table(database, &table)?;
+                (Source::Table(schema.name.clone()), Some(schema.clone()))
+            }
+            Some(TableRef::Function { name, args }) if name == "json_each" => {
+                if !(1..=2).contains(&args.len()) {
+                    return Err(PrepareError::Invalid(
+                        "Expected 'json_each(<json> [, <path>])'".to_string(),
+                    ));
+                }
+                if let Some(name) = args.iter().flat_map(Expr::columns).next() {
+                    return Err(PrepareError::Invalid(format!(
+                        "Unknown column '{}' in json_each",
+                        name
+                    )));
+                }
+                (Source::JsonEach(args), Some(json::each_schema()))
+            }
+            Some(TableRef::Function { name, .. }) => {
+                return Err(PrepareError::Invalid(format!(
+                    "Unknown table-valued function {}",
+                    name
+                )));
+            }
+        };
+
+        // `select` alone returns every column.
+        let result_columns = match select.columns.is_empty() {
+            true => vec![ast::ResultColumn::All],
+            false => select.columns,
+        };
+        let mut columns = Vec::new();
+        for result_column in result_columns {
+            let table = match &result_column {
+                ast::ResultColumn::All => match &schema {
+                    Some(schema) => schema,
+                    None => {
+                        return Err(PrepareError::Invalid("No tables specified".to_string()));
+                    }
+                },
+                ast::ResultColumn::AllOf(table) => match &schema {
+                    Some(schema) if schema.name.eq_ignore_ascii_case(table) => schema,
+                    _ => return Err(PrepareError::UnknownTable(table.clone())),
+                },
+                ast::ResultColumn::Expr { expr, alias, sql } => {
+                    check_expr(schema.as_ref(), expr)?;
+                    let name = match (alias, expr) {
+                        (Some(alias), _) => alias.clone(),
+                        (None, Expr::Column(name)) => name.clone(),
+                        (None, _) => sql.clone(),
+                    };
+                    columns.push((name, expr.clone()));
+                    continue;
+                }
+            };
+            columns.extend(
+                table
+                    .columns
+                    .iter()
+                    .map(|column| (column.name.clone(), Expr::Column(column.name.clone()))),
+            );
+        }
+
+        let order_by = select
+            .order_by
+            .map(|ast::OrderBy { expr, descending }| {
+                // A name that is not a column of the source may be an alias.
+                let expr = match &expr {
+                    Expr::Column(name)
+                        if !schema.as_ref().is_some_and(|schema| {
+                            schema.columns.iter().any(|column| column.name == *name)
+                        }) =>
+                    {
+                        columns
+                            .iter()
+                            .find(|(alias, _)| alias == name)
+                            .map_or(expr.clone(), |(_, aliased)| aliased.clone())
+                    }
+                    _ => expr,
+                };
+                check_expr(schema.as_ref(), &expr)?;
+                Ok((expr, descending))
+            })
+            .transpose()?;
+
+        let filter = select
+            .filter
+            .map(|filter| resolve_filter(schema.as_ref(), filter))
+            .transpose()?;
+
+        Ok(Statement::Select {
+            source,
+            columns,
+            filter,
+            order_by,
+        })
+    }
+
+    /// Checks an insert against its table, the users table if it names
+    /// none. Columns left out of the column list take their default value.
+    fn prepare_insert(insert: ast::Insert, database: &Database) -> Result<Statement, PrepareError> {
+        let schema = lookup_table(database, insert.table.as_deref().unwrap_or(DEFAULT_TABLE))?;
+        if schema.name == Database::CATALOG_NAME {
+            return Err(PrepareError::ProtectedTable(schema.name.clone()));
+        }
+
+        let mut prefix = match &insert.table {
+            Some(table) => format!("insert into {}", table),
+            None => "insert".to_string(),
+        };
+        let columns: Vec<usize> = match &insert.columns {
+            Some(names) => {
+                prefix = format!("{} ({})", prefix, names.join(", "));
+                names
+                    .iter()
+                    .map(|name| column_position(schema, name))
+                    .collect::<Result<_, _>>()?
+            }
+            None => (0..schema.columns.len()).collect(),
+        };
+
+        let values: Vec<Vec<Value>> = match insert.values {
+            ast::Values::Words(words) => {
+                if words.len() != columns.len() {
+                    let placeholders: Vec<String> = columns
+                        .iter()
+                        .map(|&i| format!("<{}>", schema.columns[i].name))
+                        .collect();
+                    return Err(PrepareError::Invalid(format!(
+                        "Expected '{} {}'",
+                        prefix,
+                        placeholders.join(" ")
+                    )));
+                }
+                vec![
+                    words
+                        .iter()
+                        .map(|word| Value::parse_literal(word))
+                        .collect(),
+                ]
+            }
+            ast::Values::Rows(rows) => rows
+                .iter()
+                .map(|exprs| evaluate_row(schema, &columns, exprs))
+                .collect::<Result<_, _>>()?,
+        };
+
+        let rows = values
+            .into_iter()
+            .map(|values| {
+                let mut row: Vec<Value> =
+                    schema.columns.iter().map(Column::default_value).collect();
+                for (&i, value) in columns.iter().zip(values) {
+                    row[i] = parse_value(schema, i, value)?;
+                }
+                Ok(Row { values: row })
+            })
+            .collect::<Result<_, PrepareError>>()?;
+
+        Ok(Statement::Insert {
+            table: schema.name.clone(),
+            rows,
+        })
+    }
+
+    /// Checks `update <table> set <column> = <value>, ... [where <filter>]`.
+    fn prepare_update(update: ast::Update, database: &Database) -> Result<Statement, PrepareError> {
+        let schema = lookup_table(database, &update.table)?;
+        if schema.name == Database::CATALOG_NAME {
+            return Err(PrepareError::ProtectedTable(schema.name.clone()));
+        }
+
+        Ok(Statement::Update {
+            table: schema.name.clone(),
+            assignments: update
+                .assignments
+                .into_iter()
+                .map(|(name, value)| resolve_assignment(schema, &name, value))
+                .collect::<Result<_, _>>()?,
+            filter: update
+                .filter
+                .map(|filter| resolve_filter(Some(schema), filter))
+                .transpose()?,
+        })
+    }
+
+    /// Checks `delete from <table> [where <filter>]`.
+    fn prepare_delete(delete: ast::Delete, database: &Database) -> Result<Statement, PrepareError> {
+        let schema = lookup_table(database, &delete.table)?;
+        if schema.name == Database::CATALOG_NAME {
+            return Err(PrepareError::ProtectedTable(schema.name.clone()));
+        }
+        Ok(Statement::Delete {
+            table: schema.name.clone(),
+            filter: delete
+                .filter
+                .map(|filter| resolve_filter(Some(schema), filter))
+                .transpose()?,
+        })
+    }
+
+    /// Names of the columns of the rows the statement returns, empty for
+    /// statements that return none.
+    pub fn column_names(&self) -> Vec<&str> {
+        match self {
+            Statement::Select { columns, .. } => {
+                columns.iter().map(|(name, _)| name.as_str()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns `true` if executing the statement modifies the database.
+    pub fn is_mutating(&self) -> bool {
+        match self {
+            Statement::Select { .. } => false,
+            Statement::CreateTable(_)
+            | Statement::CreateIndex(_)
+            | Statement::Insert { .. }
+            | Statement::Update { .. }
+            | Statement::Delete { .. }
+            | Statement::AlterTable { .. }
+            | Statement::DropTable { .. }
+            | Statement::DropIndex { .. } => true,
+        }
+    }
+
+    /// Executes the statement against the provided database, returning the
+    /// rows it produced.
+    pub fn execute(&self, database: &mut Database) -> Result<Vec<Row>, ExecuteError> {
+        if self.is_mutating() && database.is_read_only() {
+            return Err(ExecuteError::ReadOnly);
+        }
+
+        match self {
+            Statement::CreateTable(schema) => {
+                database.create_table(schema.clone())?;
+                Ok(Vec::new())
+            }
+            Statement::Select {
+                source,
+                columns,
+                filter,
+                order_by,
+            } => {
+                let (table_columns, mut rows) = match source {
+                    Source::Table(table) => {
+                        let schema = &database.table(table).expect("Table exists").schema;
+                        let columns = schema.columns.clone();
+                        let rows = matching_rows(database, table, filter);
+                        (columns, rows.into_iter().map(|(_, row)| row).collect())
+                    }
+                    Source::JsonEach(args) => {
+                        let no_row = Row { values: Vec::new() };
+                        let args: Vec<Value> =
+                            args.iter().map(|arg| arg.evaluate(&[], &no_row)).collect();
+                        let columns = json::each_schema().columns;
+                        let rows = json::each(&args[0], args.get(1))
+                            .into_iter()
+                            .map(|values| Row { values })
+                            .filter(|row| keeps(filter, &columns, row))
+                            .collect();
+                        (columns, rows)
+                    }
+                    Source::Nothing => {
+                        let row = Row { values: Vec::new() };
+                        let rows = match keeps(filter, &[], &row) {
+                            true => vec![row],
+                            false => Vec::new(),
+                        };
+                        (Vec::new(), rows)
+                    }
+                };
+                if let Some((expr, descending)) = order_by {
+                    let collation = expr.collation(&table_columns);
+                    let mut keyed: Vec<(Value, Row)> = rows
+                        .into_iter()
+                        .map(|row| (expr.evaluate(&table_columns, &row), row))
+                        .collect();
+                    keyed.sort_by(|(a, _), (b, _)| {
+                        let ordering = collation.compare(a, b);
+                        if *descending {
+                            ordering.reverse()
+                        } else {
+                            ordering
+                        }
+                    });
+                    rows = keyed.into_iter().map(|(_, row)| row).collect();
+                }
+                Ok(rows
+                    .iter()
+                    .map(|row| Row {
+                        values: columns
+                            .iter()
+                            .map(|(_, expr)| expr.evaluate(&table_columns, row))
+                            .collect(),
+                    })
+                    .collect())
+            }
+            Statement::CreateIndex(definition) => {
+                database.create_index(definition)?;
+                Ok(Vec::new())
+            }
+            Statement::Insert { table, rows } => {
+                for row in rows {
+                    database.insert(table, row)?;
+                }
+                Ok(Vec::new())
+            }
+            Statement::Update {
+                table,
+                assignments,
+                filter,
+            } => {
+                let schema = database.table(table).expect("Table exists").schema.clone();
+                for (rowid, old) in matching_rows(database, table, filter) {
+                    let mut row = old.clone();
+                    for (column, value) in assignments {
+                        let value = value.evaluate(&schema.columns, &old);
+                        row.values[*column] = parse_value(&schema, *column, value)
+                            .map_err(ExecuteError::InvalidValue)?;
+                    }
+                    database.update(table, rowid, &row)?;
+                }
+                Ok(Vec::new())
+            }
+            Statement::Delete { table, filter } => {
+                let rowids: Vec<i64> = matching_rows(database, table, filter)
+                    .into_iter()
+                    .map(|(rowid, _)| rowid)
+                    .collect();
+                database.delete(table, &rowids)?;
+                Ok(Vec::new())
+            }
+            Statement::AlterTable { table, alteration } => {
+                database.alter_table(table, alteration)?;
+                Ok(Vec::new())
+            }
+            Statement::DropTable { table } => {
+                database.drop_table(table)?;
+                Ok(Vec::new())
+            }
+            Statement::DropIndex { index } => {
+                database.drop_index(index)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+/// Returns the rows of `table`, with their rowid, for which `filter` is
+/// true, or every row if there is no filter. A filter comparing a column to
+/// a value is answered by an index starting with the column when there is
+/// one.
+fn matching_rows(database: &mut Database, table: &str, filter: &Option<Expr>) -> Vec<(i64, Row)> {
+    let columns = database
+        .table(table)
+        .expect("Table exists")
+        .schema
+        .columns
+        .clone();
+    if let Some((column, value)) = filter
+        .as_ref()
+        .and_then(|filter| equality(&columns, filter))
+    {
+        return database.find(table, column, &value);
+    }
+    let mut cursor = database.table_start(table);
+    std::iter::from_fn(|| cursor.next_with_rowid())
+        .filter(|(_, row)| keeps(filter, &columns, row))
+        .collect()
+}
+
+/// Returns `true` if there is no filter or it is true for `row`: a NULL
+/// filter drops the row.
+fn keeps(filter: &Option<Expr>, columns: &[Column], row: &Row) -> bool {
+    filter
+        .as_ref()
+        .is_none_or(|filter| truth(&filter.evaluate(columns, row)) == Some(true))
+}
+
+/// The column and value of a `<column> = <value>` filter, which matches no
+/// row when the value is NULL.
+fn equality(columns: &[Column], filter: &Expr) -> Option<(usize, Value)> {
+    let Expr::Binary {
+        op: BinaryOp::Eq,
+        left,
+        right,
+    } = filter
+    else {
+        return None;
+    };
+    match (&**left, &**right) {
+        (Expr::Column(name), Expr::Literal(value)) if *value != Value::Null => columns
+            .iter()
+            .position(|column| column.name == *name)
+            .map(|column| (column, value.clone())),
+        _ => None,
+    }
+}
+
+/// Index of the column of `schema` named `name`.
+fn column_position(schema: &Schema, name: &str) -> Result<usize, PrepareError> {
+    schema
+        .columns
+        .iter()
+        .position(|column| column.name == name)
+        .ok_or_else(|| {
+            PrepareError::Invalid(format!("Table {} has no column '{}'", schema.name, name))
+        })
+}
+
+/// Evaluates a row of `values (...)` given for `columns`, which may not
+/// read other columns.
+fn evaluate_row(
+    schema: &Schema,
+    columns: &[usize],
+    exprs: &[Expr],
+) -> Result<Vec<Value>, PrepareError> {
+    if exprs.len() != columns.len() {
+        return Err(PrepareError::Invalid(format!(
+            "{} values for {} columns",
+            exprs.len(),
+            columns.len()
+        )));
+    }
+    columns
+        .iter()
+        .zip(exprs)
+        .map(|(&i, expr)| match expr.columns().is_empty() {
+            true => Ok(expr.evaluate(&[], &Row { values: Vec::new() })),
+            false => Err(PrepareError::Invalid(format!(
+                "Expected a value for column {}",
+                schema.columns[i].name
+            ))),
+        })
+        .collect()
+}
+
+/// Checks that `expr` only reads columns of `schema`, if there is one, and
+/// names known collations.
+fn check_expr(schema: Option<&Schema>, expr: &Expr) -> Result<(), PrepareError> {
+    let columns = schema.map_or(&[][..], |schema| &schema.columns);
+    if let Some(name) = expr
+        .columns()
+        .into_iter()
+        .find(|name| !columns.iter().any(|column| column.name == *name))
+    {
+        return Err(PrepareError::Invalid(match schema {
+            Some(schema) => format!("Table {} has no column '{}'", schema.name, name),
+            None => format!("Unknown column '{}'", name),
+        }));
+    }
+    if let Some(name) = expr
+        .collations()
+        .into_iter()
+        .find(|name| Collation::lookup(name).is_none())
+    {
+        return Err(PrepareError::UnknownCollation(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Resolves `<column> = <value>` to the column's index and the value,
+/// which may read the row's other columns.
+fn resolve_assignment(
+    schema: &Schema,
+    name: &str,
+    value: Expr,
+) -> Result<(usize, Expr), PrepareError> {
+    let index = column_position(schema, name)?;
+    check_expr(Some(schema), &value)?;
+    Ok((index, value))
+}
+
+/// Checks a `where` filter on the rows of `schema`, if there is one.
+fn resolve_filter(schema: Option<&Schema>, filter: Expr) -> Result<Expr, PrepareError> {
+    let filter = match schema {
+        Some(schema) => compared_as_stored(schema, filter),
+        None => filter,
+    };
+    check_expr(schema, &filter)?;
+    Ok(filter)
+}
+
+/// Converts the literals columns are compared with to the columns'
+/// affinity, whichever side of the comparison the column is on, so that
+/// `id = '1'` and `'1' = id` both match the integer 1. The items of an `IN`
+/// list and the bounds of a `BETWEEN` take the affinity of their operand.
+fn compared_as_stored(schema: &Schema, expr: Expr) -> Expr {
+    let affinity = |expr: &Expr| match expr {
+        Expr::Column(name) => schema
+            .columns
+            .iter()
+            .find(|column| column.name == *name)
+            .map(|column| column.affinity),
+        _ => None,
+    };
+    let convert = |affinity: Option<Affinity>, expr: Expr| match (affinity, expr) {
+        (Some(affinity), Expr::Literal(value)) => Expr::Literal(affinity.apply(value)),
+        (_, expr) => compared_as_stored(schema, expr),
+    };
+    match expr {
+        Expr::Unary { op, operand } => Expr::Unary {
+            op,
+            operand: Box::new(compared_as_stored(schema, *operand)),
+        },
+        Expr::Binary {
+            op:
+                op @ (BinaryOp::Eq
+                | BinaryOp::NotEq
+                | BinaryOp::Is
+                | BinaryOp::IsNot
+                | BinaryOp::Lt
+                | BinaryOp::LtEq
+                | BinaryOp::Gt
+                | BinaryOp::GtEq),
+            left,
+            right,
+        } => {
+            let (left_affinity, right_affinity) = (affinity(&left), affinity(&right));
+            Expr::Binary {
+                op,
+                left: Box::new(convert(right_affinity, *left)),
+                right: Box::new(convert(left_affinity, *right)),
+            }
+        }
+        Expr::Binary { op, left, right } => Expr::Binary {
+            op,
+            left: Box::new(compared_as_stored(schema, *left)),
+            right: Box::new(compared_as_stored(schema, *right)),
+        },
+        Expr::In { operand, list } => {
+            let operand_affinity = affinity(&operand);
+            Expr::In {
+                operand: Box::new(compared_as_stored(schema, *operand)),
+                list: list
+                    .into_iter()
+                    .map(|item| convert(operand_affinity, item))
+                    .collect(),
+            }
+        }
+        Expr::Between { operand, low, high } => {
+            let operand_affinity = affinity(&operand);
+            Expr::Between {
+                operand: Box::new(compared_as_stored(schema, *operand)),
+                low: Box::new(convert(operand_affinity, *low)),
+                high: Box::new(convert(operand_affinity, *high)),
+            }
+        }
+        expr => expr,
+    }
+}
+
+/// Converts a value given for column `index` of `schema` to the column's
+/// affinity, checking it fits the column.
+fn parse_value(schema: &Schema, index: usize, value: Value) -> Result<Value, PrepareError> {
+    let column = &schema.columns[index];
+    let value = column.affinity.apply(value);
+    let length = match &value {
+        Value::Text(text) => text.chars().count(),
+        Value::Blob(bytes) => bytes.len(),
+        _ => 0,
+    };
+    // The tutorial's users table numbers its users from 0; a NULL id asks
+    // for the next free one.
+    if schema.name == Schema::USERS_NAME
+        && schema.primary_key() == Some(index)
+        && !matches!(value, Value::Integer(id) if id >= 0)
+        && value != Value::Null
+    {
+        Err(PrepareError::InvalidId)
+    } else if length > column.max_length() {
+        Err(PrepareError::StringTooLong)
+    } else {
+        Ok(value)
+    }
+}
+
+/// Checks that a new table name does not use the prefix of the tables the
+/// database manages itself.
+fn check_table_name(name: &str) -> Result<(), PrepareError> {
+    if name.starts_with(Database::RESERVED_PREFIX) {
+        return Err(PrepareError::Invalid(format!(
+            "Table name {} is reserved for internal use",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the foreign keys of `schema` reference a primary key or a
+/// unique column of an existing table, or of the table itself.
+fn check_foreign_keys(schema: &Schema, database: &Database) -> Result<(), PrepareError> {
+    for foreign_key in &schema.foreign_keys {
+        let parent = if foreign_key.parent == schema.name {
+            schema
+        } else {
+            lookup_table(database, &foreign_key.parent)?
+        };
+        let referenced = parent.referenced_column(foreign_key).filter(|&column| {
+            parent.primary_key() == Some(column) || parent.unique.contains(&vec![column])
+        });
+        if referenced.is_none() {
+            return Err(PrepareError::Invalid(format!(
+                "Foreign key {}.{} must reference a primary key or unique column of {}",
+                schema.name, schema.columns[foreign_key.column].name, parent.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the collations used by `schema` are built in or registered.
+fn check_collations(schema: &Schema) -> Result<(), PrepareError> {
+    match schema
+        .collations()
+        .into_iter()
+        .find(|name| Collation::lookup(name).is_none())
+    {
+        Some(name) => Err(PrepareError::UnknownCollation(name.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Looks up a table, refusing it while a collation it uses is not
+/// registered.
+fn lookup_table<'a>(database: &'a Database, name: &str) -> Result<&'a Schema, PrepareError> {
+    let schema = database
+        .table(name)
+        .map(|table| &table.schema)
+        .ok_or_else(|| PrepareError::UnknownTable(name.to_string()))?;
+    check_collations(schema)?;
+    Ok(schema)
+}
@@ -0,0 +1,130 @@
+use crate::btree::{BTree, IndexKey, LeafNode, Node, read_node};
+use crate::{Collation, Pager, Row, Schema, Value};
+
+/// A cursor for iterating over the rows of a table in key order.
+pub struct Cursor<'a> {
+    pager: &'a mut Pager,
+    schema: &'a Schema,
+    leaf: LeafNode<i64>,
+    cell_num: usize,
+}
+
+impl<'a> Cursor<'a> {
+    /// Positions a cursor on the first row of `table`.
+    pub(crate) fn table_start(pager: &'a mut Pager, table: &'a Table) -> Self {
+        let page_num = table.btree().first_leaf(pager);
+        let leaf = read_leaf(pager, page_num);
+        Cursor {
+            pager,
+            schema: &table.schema,
+            leaf,
+            cell_num: 0,
+        }
+    }
+}
+
+impl Cursor<'_> {
+    /// Returns the next row along with its rowid.
+    pub fn next_with_rowid(&mut self) -> Option<(i64, Row)> {
+        // Leaves can be left empty by deletes, so keep following the chain
+        // until a cell turns up.
+        while self.cell_num >= self.leaf.cells.len() {
+            if self.leaf.next_leaf == 0 {
+                return None;
+            }
+            self.leaf = read_leaf(self.pager, self.leaf.next_leaf);
+            self.cell_num = 0;
+        }
+
+        let cell = &self.leaf.cells[self.cell_num];
+        self.cell_num += 1;
+        Some((cell.key, Row::deserialize(self.schema, &cell.payload)))
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_rowid().map(|(_, row)| row)
+    }
+}
+
+fn read_leaf(pager: &mut Pager, page_num: usize) -> LeafNode<i64> {
+    match read_node(pager, page_num) {
+        Node::Leaf(leaf) => leaf,
+        Node::Internal(_) => panic!("Corrupt table: page {} is not a leaf", page_num),
+    }
+}
+
+/// A table stored in the database: its definition and the root page of
+/// the B-tree holding its rows, keyed by rowid.
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub schema: Schema,
+    pub root_page: usize,
+}
+
+impl Table {
+    pub(crate) fn btree(&self) -> BTree {
+        BTree::new(self.root_page)
+    }
+}
+
+/// An index over some columns of a table, stored in its own B-tree whose
+/// keys are the indexed values followed by the rowid of their row.
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<usize>,
+    /// Collation of each indexed column. Entries hold the sort keys of
+    /// their text, so that they are ordered and matched by collation.
+    pub collations: Vec<Option<String>>,
+    pub unique: bool,
+    pub root_page: usize,
+    /// The `create index` statement, `None` for automatic indexes.
+    pub sql: Option<String>,
+}
+
+impl Index {
+    /// Start of the names of automatic indexes.
+    pub(crate) const AUTOMATIC_PREFIX: &str = "rust_sqlite_autoindex_";
+
+    pub(crate) fn btree(&self) -> BTree<IndexKey> {
+        BTree::new(self.root_page)
+    }
+
+    /// Returns `true` for the indexes created along with a table to enforce
+    /// its `UNIQUE` constraints.
+    pub fn is_automatic(&self) -> bool {
+        self.sql.is_none()
+    }
+
+    /// Returns the collations of the `columns` of a table's `schema`.
+    pub(crate) fn column_collations(schema: &Schema, columns: &[usize]) -> Vec<Option<String>> {
+        columns
+            .iter()
+            .map(|&i| schema.columns[i].collation.clone())
+            .collect()
+    }
+
+    /// Returns the entry pointing at `row`.
+    pub(crate) fn key(&self, rowid: i64, row: &Row) -> IndexKey {
+        let values = self.columns.iter().map(|&i| row.values[i].clone());
+        self.entry(values.collect(), rowid)
+    }
+
+    /// Returns the entry for the values of the first indexed columns,
+    /// converted to the sort keys of their collation.
+    pub(crate) fn entry(&self, values: Vec<Value>, rowid: i64) -> IndexKey {
+        IndexKey {
+            values: values
+                .iter()
+                .zip(&self.collations)
+                .map(|(value, collation)| Collation::named(collation.as_deref()).sort_key(value))
+                .collect(),
+            rowid,
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt;
+
+use crate::{PrepareError, Value};
+
+/// Words with a meaning of their own in SQL. They are matched ignoring
+/// case and must be quoted to be used as names.
+const KEYWORDS: [&str; 46] = [
+    "add",
+    "alter",
+    "and",
+    "as",
+    "asc",
+    "autoincrement",
+    "between",
+    "by",
+    "cascade",
+    "check",
+    "collate",
+    "column",
+    "constraint",
+    "create",
+    "current_date",
+    "current_time",
+    "current_timestamp",
+    "default",
+    "delete",
+    "desc",
+    "drop",
+    "foreign",
+    "from",
+    "in",
+    "index",
+    "insert",
+    "into",
+    "is",
+    "like",
+    "not",
+    "null",
+    "on",
+    "or",
+    "order",
+    "primary",
+    "references",
+    "rename",
+    "restrict",
+    "select",
+    "set",
+    "table",
+    "to",
+    "unique",
+    "update",
+    "values",
+    "where",
+];
+
+/// Symbols, longest first so that `<=` is not read as `<`.
+const SYMBOLS: [&str; 21] = [
+    "->>", "->", "<>", "<=", ">=", "!=", "==", "||", "<", ">", "=", "+", "-", "*", "/", "%", "(",
+    ")", ",", ".", ";",
+];
+
+/// A token of SQL text and the byte offsets it starts and ends at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub offset: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// One of the reserved words, lowercase.
+    Keyword(&'static str),
+    /// A name, as written or with its quotes (`"`, `` ` `` or `[]`) removed.
+    Identifier(String),
+    /// A `'quoted'` string, with doubled quotes unescaped.
+    String(String),
+    /// An `x'hex'` literal.
+    Blob(Vec<u8>),
+    /// An integer or real literal.
+    Number(Value),
+    /// `?`, `?<number>`, `:<name>`, `@<name>` or `$<name>`, as written.
+    Parameter(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Keyword(keyword) => write!(f, "{}", keyword),
+            TokenKind::Identifier(name) => write!(f, "{}", name),
+            TokenKind::String(text) => write!(f, "'{}'", text.replace('\'', "''")),
+            TokenKind::Blob(bytes) => write!(f, "{}", Value::Blob(bytes.clone())),
+            TokenKind::Number(value) => write!(f, "{}", value),
+            TokenKind::Parameter(parameter) => write!(f, "{}", parameter),
+            TokenKind::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+/// Splits SQL text into tokens, skipping whitespace and comments.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, PrepareError> {
+    Lexer::new(input).collect()
+}
+
+/// Writes a name so that it reads back as the same identifier: as is if it
+/// is a word that is not a keyword, in double quotes otherwise.
+pub(crate) fn quote_identifier(name: &str) -> String {
+    let is_word = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && word_length(name) == name.len();
+    let is_keyword = KEYWORDS
+        .iter()
+        .any(|keyword| name.eq_ignore_ascii_case(keyword));
+    if is_word && !is_keyword {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// Reads the tokens of SQL text one at a time, so that a statement can be
+/// recognized before the rest of it is read.
+pub struct Lexer<'a> {
+    input: &'a str,
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Lexer<'a> {
+        Lexer {
+            input,
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    /// Offset of the next token to read, the end of the input once every
+    /// token is read, or the start of the invalid token reading stopped at.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Continues reading at `offset`, e.g. past text that is not a token.
+    pub(crate) fn resume_at(&mut self, offset: usize) {
+        self.offset = offset;
+        self.failed = false;
+    }
+
+    /// Moves past whitespace, `-- line` comments and `/* block */`
+    /// comments. An unterminated block comment runs to the end of input.
+    fn skip_blanks(&mut self) {
+        loop {
+            let rest = &self.input[self.offset..];
+            let trimmed = rest.trim_start();
+            self.offset += rest.len() - trimmed.len();
+            let skipped = if trimmed.starts_with("--") {
+                trimmed.find('\n').unwrap_or(trimmed.len())
+            } else if let Some(body) = trimmed.strip_prefix("/*") {
+                body.find("*/").map_or(trimmed.len(), |end| end + 4)
+            } else {
+                return;
+            };
+            self.offset += skipped;
+        }
+    }
+
+    /// Reads the token at the start of `rest`, returning it with its length.
+    fn read(&self, rest: &str) -> Result<(TokenKind, usize), PrepareError> {
+        let error =
+            |expected: &str, found: Option<&str>, offset: usize| PrepareError::SyntaxError {
+                expected: expected.to_string(),
+                found: found.map(str::to_string),
+                offset,
+            };
+        let unterminated =
+            |quote: char| error(&format!("a closing {}", quote), None, self.input.len());
+
+        let c = rest
+            .chars()
+            .next()
+            .expect("Blanks are skipped before reading");
+        let next = rest[c.len_utf8()..].chars().next();
+        if c == '\'' || (matches!(c, 'x' | 'X') && next == Some('\'')) {
+            let start = if c == '\'' { 1 } else { 2 };
+            let end = quoted_end(&rest[start..], '\'').ok_or_else(|| unterminated('\''))?;
+            let literal = &rest[..start + end];
+            return match Value::parse_literal(literal) {
+                Value::Blob(bytes) => Ok((TokenKind::Blob(bytes), literal.len())),
+                Value::Text(text) if c == '\'' => Ok((TokenKind::String(text), literal.len())),
+                _ => Err(error(
+                    "an even number of hex digits",
+                    Some(literal),
+                    self.offset,
+                )),
+            };
+        }
+        if c.is_ascii_digit() || (c == '.' && next.is_some_and(|c| c.is_ascii_digit())) {
+            let length = number_length(rest);
+            return match Value::parse_literal(&rest[..length]) {
+                value @ (Value::Integer(_) | Value::Real(_)) => {
+                    Ok((TokenKind::Number(value), length))
+                }
+                _ => Err(error("a number", Some(&rest[..length]), self.offset)),
+            };
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let length = word_length(rest);
+            let word = &rest[..length];
+            let kind = match KEYWORDS
+                .iter()
+                .find(|keyword| word.eq_ignore_ascii_case(keyword))
+            {
+                Some(keyword) => TokenKind::Keyword(keyword),
+                None => TokenKind::Identifier(word.to_string()),
+            };
+            return Ok((kind, length));
+        }
+        if let Some(close) = match c {
+            '"' => Some('"'),
+            '`' => Some('`'),
+            '[' => Some(']'),
+            _ => None,
+        } {
+            let end = if close == ']' {
+                rest[1..].find(']').map(|end| end + 1)
+            } else {
+                quoted_end(&rest[1..], close)
+            }
+            .ok_or_else(|| unterminated(close))?;
+            let doubled = close.to_string().repeat(2);
+            let name = rest[1..end].replace(&doubled, &close.to_string());
+            return Ok((TokenKind::Identifier(name), end + 1));
+        }
+        if c == '?' {
+            let length = 1 + rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - 1);
+            return Ok((TokenKind::Parameter(rest[..length].to_string()), length));
+        }
+        if matches!(c, ':' | '@' | '$') {
+            let length = 1 + word_length(&rest[1..]);
+            if length == 1 {
+                let found = next.map(|next| next.to_string());
+                return Err(error("a parameter name", found.as_deref(), self.offset + 1));
+            }
+            return Ok((TokenKind::Parameter(rest[..length].to_string()), length));
+        }
+        match SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            Some(symbol) => Ok((TokenKind::Symbol(symbol), symbol.len())),
+            None => Err(error("a token", Some(&c.to_string()), self.offset)),
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, PrepareError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        self.skip_blanks();
+        let rest = &self.input[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let offset = self.offset;
+        Some(match self.read(rest) {
+            Ok((kind, length)) => {
+                self.offset += length;
+                Ok(Token {
+                    kind,
+                    offset,
+                    end: self.offset,
+                })
+            }
+            Err(error) => {
+                // Nothing after an invalid token is read.
+                self.failed = true;
+                Err(error)
+            }
+        })
+    }
+}
+
+/// Length of a quoted body up to and including the closing quote,
+/// skipping doubled quotes.
+fn quoted_end(body: &str, quote: char) -> Option<usize> {
+    let mut chars = body.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == quote {
+            if chars.peek().is_some_and(|(_, next)| *next == quote) {
+                chars.next();
+            } else {
+                return Some(i + 1);
+            }
+        }
+    }
+    None
+}
+
+/// Length of the run of letters, digits and underscores at the start of
+/// `input`.
+fn word_length(input: &str) -> usize {
+    input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(input.len())
+}
+
+/// Length of the number at the start of `input`, exponent included.
+fn number_length(input: &str) -> usize {
+    let bytes = input.as_bytes();
+    let mut end = 0;
+    while end < bytes.len() && (bytes[end].is_ascii_digit() || bytes[end] == b'.') {
+        end += 1;
+    }
+    if end < bytes.len() && matches!(bytes[end], b'e' | b'E') {
+        let mut exponent = end + 1;
+        if exponent < bytes.len() && matches!(bytes[exponent], b'+' | b'-') {
+            exponent += 1;
+        }
+        if exponent < bytes.len() && bytes[exponent].is_ascii_digit() {
+            end = exponent;
+            while end < bytes.len() && bytes[end].is_ascii_digit() {
+                end += 1;
+            }
+        }
+    }
+    end
+}
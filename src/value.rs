@@ -0,0 +1,237 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A dynamically typed SQL value. Any column can hold a value of any
+/// type; the column's affinity only decides which conversions are
+/// attempted when a value is stored.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// Parses a literal as typed in a statement: `null`, an integer, a real,
+    /// a `'quoted'` string, an `x'hex'` blob or, failing all of those, the
+    /// bare word as text.
+    pub fn parse_literal(literal: &str) -> Value {
+        if literal.eq_ignore_ascii_case("null") {
+            return Value::Null;
+        }
+        if let Some(value) = parse_number(literal) {
+            return value;
+        }
+        if let Some(hex) = literal
+            .strip_prefix(['x', 'X'])
+            .and_then(|rest| rest.strip_prefix('\''))
+            .and_then(|rest| rest.strip_suffix('\''))
+            && let Some(bytes) = decode_hex(hex)
+        {
+            return Value::Blob(bytes);
+        }
+        if let Some(text) = literal
+            .strip_prefix('\'')
+            .and_then(|rest| rest.strip_suffix('\''))
+        {
+            return Value::Text(text.replace("''", "'"));
+        }
+        Value::Text(literal.to_string())
+    }
+
+    /// Name of the value's storage class.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Integer(_) => "integer",
+            Value::Real(_) => "real",
+            Value::Text(_) => "text",
+            Value::Blob(_) => "blob",
+        }
+    }
+
+    /// Rank of the storage class in the sort order:
+    /// NULL < INTEGER and REAL < TEXT < BLOB.
+    fn class_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+}
+
+/// Bounds of the reals that convert to an `i64` exactly: [-2^63, 2^63).
+const I64_MIN_AS_F64: f64 = -9_223_372_036_854_775_808.0;
+const I64_END_AS_F64: f64 = 9_223_372_036_854_775_808.0;
+
+/// Compares an integer with a real without losing precision on either side.
+fn compare_integer_real(integer: i64, real: f64) -> Ordering {
+    if real.is_nan() {
+        return Ordering::Greater;
+    }
+    if real >= I64_END_AS_F64 {
+        return Ordering::Less;
+    }
+    if real < I64_MIN_AS_F64 {
+        return Ordering::Greater;
+    }
+    match integer.cmp(&(real.floor() as i64)) {
+        Ordering::Equal if real.fract() > 0.0 => Ordering::Less,
+        ordering => ordering,
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Real(a), Value::Real(b)) => a.total_cmp(b),
+            (Value::Integer(a), Value::Real(b)) => compare_integer_real(*a, *b),
+            (Value::Real(a), Value::Integer(b)) => compare_integer_real(*b, *a).reverse(),
+            (Value::Text(a), Value::Text(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            _ => self.class_rank().cmp(&other.class_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Integer(value) => write!(f, "{}", value),
+            // Debug keeps the fractional part of whole numbers, e.g. `1.0`.
+            Value::Real(value) => write!(f, "{:?}", value),
+            Value::Text(value) => write!(f, "{}", value),
+            Value::Blob(bytes) => {
+                write!(f, "x'")?;
+                for byte in bytes {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
+        }
+    }
+}
+
+/// The preferred storage class of a column, derived from its declared type
+/// with the same rules SQLite uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Affinity {
+    Integer,
+    Real,
+    Numeric,
+    Text,
+    Blob,
+}
+
+impl Affinity {
+    /// Derives the affinity of a declared type, such as `varchar(32)` or
+    /// `unsigned big int`. A column declared without a type has no affinity.
+    pub fn from_declared_type(declared_type: &str) -> Affinity {
+        let declared_type = declared_type.to_ascii_uppercase();
+        if declared_type.contains("INT") {
+            Affinity::Integer
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|name| declared_type.contains(name))
+        {
+            Affinity::Text
+        } else if declared_type.is_empty() || declared_type.contains("BLOB") {
+            Affinity::Blob
+        } else if ["REAL", "FLOA", "DOUB"]
+            .iter()
+            .any(|name| declared_type.contains(name))
+        {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    /// Converts a value to the column's preferred storage class when that
+    /// can be done without losing information.
+    pub fn apply(&self, value: Value) -> Value {
+        match (self, value) {
+            (Affinity::Blob, value) => value,
+            (Affinity::Text, value @ (Value::Integer(_) | Value::Real(_))) => {
+                Value::Text(value.to_string())
+            }
+            (Affinity::Integer | Affinity::Numeric, Value::Text(text)) => {
+                match parse_number(text.trim()) {
+                    Some(number) => integral(number),
+                    None => Value::Text(text),
+                }
+            }
+            (Affinity::Integer | Affinity::Numeric, value @ Value::Real(_)) => integral(value),
+            (Affinity::Real, Value::Integer(integer)) => Value::Real(integer as f64),
+            (Affinity::Real, Value::Text(text)) => match parse_number(text.trim()) {
+                Some(Value::Integer(integer)) => Value::Real(integer as f64),
+                Some(real) => real,
+                None => Value::Text(text),
+            },
+            (_, value) => value,
+        }
+    }
+}
+
+/// Turns a real holding a whole number into an integer.
+fn integral(value: Value) -> Value {
+    match value {
+        Value::Real(real)
+            if real.fract() == 0.0 && (I64_MIN_AS_F64..I64_END_AS_F64).contains(&real) =>
+        {
+            Value::Integer(real as i64)
+        }
+        value => value,
+    }
+}
+
+/// Parses an integer or real literal. Words that Rust would accept as
+/// floats, like `inf` or `nan`, are not numbers in SQL.
+fn parse_number(literal: &str) -> Option<Value> {
+    let digits = literal.strip_prefix(['+', '-']).unwrap_or(literal);
+    if !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    if let Ok(integer) = literal.parse::<i64>() {
+        return Some(Value::Integer(integer));
+    }
+    if digits
+        .chars()
+        .all(|c| c.is_ascii_digit() || "eE.+-".contains(c))
+        && let Ok(real) = literal.parse::<f64>()
+    {
+        return Some(Value::Real(real));
+    }
+    None
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
@@ -1,11 +1,15 @@
 #[cfg(test)]
 mod tests {
+    use std::io::{Seek, SeekFrom, Write};
     use std::path::{Path, PathBuf};
     use std::str::FromStr;
 
     use assert_cmd::Command;
     use predicates::prelude::*;
-    use rust_sqlite::{EMAIL_SIZE, TABLE_MAX_ROWS, USERNAME_SIZE};
+    use rust_sqlite::{
+        Collation, Database, EMAIL_SIZE, Expr, PAGE_SIZE, Pager, PrepareError, READ_AHEAD_PAGES,
+        ROWS_PER_PAGE, Statement, TABLE_MAX_ROWS, TokenKind, USERNAME_SIZE, ast, parse, tokenize,
+    };
     use tempfile::NamedTempFile;
 
     // Helper function to run the command with a temporary database file
@@ -21,7 +25,16 @@ mod tests {
     }
 
     fn run_commands_with_args<T: AsRef<str>>(commands: &[T], db_path: &Path) -> Command {
+        run_commands_with_flags(commands, db_path, &[])
+    }
+
+    fn run_commands_with_flags<T: AsRef<str>>(
+        commands: &[T],
+        db_path: &Path,
+        flags: &[&str],
+    ) -> Command {
         let mut cmd = Command::cargo_bin("rust-sqlite").expect("Failed to run command");
+        cmd.args(flags);
         cmd.arg(db_path.to_str().expect("Invalid path"));
 
         let input = commands
@@ -48,11 +61,231 @@ mod tests {
         cmd.assert().success().stdout(expected);
     }
 
+    #[test]
+    fn it_inserts_rows_from_values_lists() {
+        let mut cmd = run_commands(&[
+            "INSERT INTO users (id, username, email) VALUES (1, 'Mary Ann', 'm@x.io')",
+            "insert into users (email, id, username) values ('o@x.io', 2, 'O''Brien');",
+            "insert into users values (3, 'a' || 'b', lower('AB@X.IO')), (4, 'c d', null)",
+            "insert into users values (5, 'e', 'e@x.io'), (1, 'dup', 'dup@x.io')",
+            "insert into users (id, username) values (6, 'f', 'f@x.io')",
+            "insert into users values (6, username, 'f@x.io')",
+            "insert into users values (6, 'f', 'f@x.io'",
+            "select",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            // The rows before a failing one are kept.
+            "db > Error: Duplicate key.",
+            "db > Syntax error: 3 values for 2 columns",
+            "db > Syntax error: Expected a value for column username",
+            "db > Syntax error: Expected ')' but found end of input at offset 42",
+            "db > (1, Mary Ann, m@x.io)",
+            "(2, O'Brien, o@x.io)",
+            "(3, ab, ab@x.io)",
+            "(4, c d, NULL)",
+            "(5, e, e@x.io)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_selects_columns_and_expressions() {
+        let mut cmd = run_commands(&[
+            "insert 1 alice Alice@Example.com",
+            "insert 2 bob bob@example.com",
+            "SELECT username, upper(email) AS e FROM users",
+            ".headers on",
+            "select id * 10 as tens, username || '!' from users order by tens desc",
+            "select users.*, id from users order by e",
+            "select e.* from users",
+            "select 1 + 2 answer, 'x'",
+            "select * order by 1",
+            "select nickname from users",
+            ".headers off",
+            "select",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > (alice, ALICE@EXAMPLE.COM)",
+            "(bob, BOB@EXAMPLE.COM)",
+            "Executed.",
+            "db > db > (tens, username || '!')",
+            "(20, bob!)",
+            "(10, alice!)",
+            "Executed.",
+            "db > Syntax error: Table users has no column 'e'",
+            "db > Unknown table e.",
+            "db > (answer, 'x')",
+            "(3, x)",
+            "Executed.",
+            "db > Syntax error: No tables specified",
+            "db > Syntax error: Table users has no column 'nickname'",
+            "db > db > (1, alice, Alice@Example.com)",
+            "(2, bob, bob@example.com)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_updates_columns_from_expressions() {
+        let mut cmd = run_commands(&[
+            "create table t (id integer primary key, n integer, s text(8), c text)",
+            "insert into t 1 10 ab cd",
+            "insert into t 2 20 ef gh",
+            "update t set n = 1 + 2 where id = 1",
+            "update t set s = upper('x'), c = s || c where id = 2",
+            "update t set n = n + 1, s = n * 2",
+            "update t set s = s || 'abcdefgh'",
+            "update t set id = id - 5 where id = 2",
+            "update t set n = nosuch + 1",
+            "select * from t",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            // Every assignment reads the row as it was before the update.
+            "db > Executed.",
+            "db > Error: String is too long.",
+            "db > Executed.",
+            "db > Syntax error: Table t has no column 'nosuch'",
+            "db > (-3, 21, 40, efgh)",
+            "(1, 4, 6, cd)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_filters_rows_with_where_expressions() {
+        let mut cmd = run_commands(&[
+            "insert 1 alice alice@x.io",
+            "insert 2 bob null",
+            "insert 3 carol carol@x.io",
+            "insert 4 dave dave@x.io",
+            "select username from users where id >= 2 and id * 2 < 8 and username <> 'carol'",
+            "select id from users where id in (1, 4) or username like 'c%'",
+            "select id from users where id between 2 and 3",
+            "select id from users where email is null",
+            "select id from users where email = null or not email like '%x.io'",
+            // NOT IN with a NULL in the list is never true.
+            "select id from users where id not in (1, null)",
+            "select id from users where id not between 2 and 3 order by id desc",
+            "update users set email = 'b@x.io' where email is null",
+            "delete from users where username in ('alice', 'dave')",
+            "select",
+            "select id from users where nickname = 1",
+            "select id from users where id = = 1",
+            "select id from users where username = nosuch",
+            // Literals take the affinity of the column on either side.
+            "select id from users where '2' = id or id in ('3', 'x')",
+            "select id from users where id between '1' and '2.0'",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (bob)",
+            "Executed.",
+            "db > (1)",
+            "(3)",
+            "(4)",
+            "Executed.",
+            "db > (2)",
+            "(3)",
+            "Executed.",
+            "db > (2)",
+            "Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (4)",
+            "(1)",
+            "Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (2, bob, b@x.io)",
+            "(3, carol, carol@x.io)",
+            "Executed.",
+            "db > Syntax error: Table users has no column 'nickname'",
+            "db > Syntax error: Expected an expression but found '=' at offset 32",
+            "db > Syntax error: Table users has no column 'nosuch'",
+            "db > (2)",
+            "(3)",
+            "Executed.",
+            "db > (2)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_ends_word_inserts_at_a_semicolon() {
+        let mut cmd = run_commands(&[
+            "insert 1 a b;",
+            "insert 2 'c d' e@x.io ; ",
+            "insert 3 e f; select",
+            "insert 4 ü {\"k\":1};",
+            "select",
+            ".exit",
+        ]);
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Syntax error: Expected the end of the statement but found 'select' at offset 14",
+            "db > Executed.",
+            "db > (1, a, b)",
+            "(2, c d, e@x.io)",
+            "(4, ü, {\"k\":1})",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    // Rows are stored in as few bytes as their values need, so the table
+    // capacity only holds for rows with the longest username and email.
+    fn longest_user(i: usize) -> (String, String) {
+        let username = format!("user{i:0>width$}", width = USERNAME_SIZE - 4);
+        let email = format!("{i:0>width$}@example.com", width = EMAIL_SIZE - 12);
+        (username, email)
+    }
+
     #[test]
     fn it_prints_error_message_when_table_is_full() {
         let mut commands = Vec::new();
         for i in 0..TABLE_MAX_ROWS + 1 {
-            commands.push(format!("insert {i} user{i} person{i}@example.com"));
+            let (username, email) = longest_user(i);
+            commands.push(format!("insert {i} {username} {email}"));
         }
         commands.push(String::from_str(".exit").unwrap());
 
@@ -68,8 +301,9 @@ mod tests {
         let mut commands = Vec::new();
         let mut expected = Vec::new();
         for i in 0..TABLE_MAX_ROWS {
-            commands.push(format!("insert {i} user{i} person{i}@example.com"));
-            expected.push(format!("({i}, user{i}, person{i}@example.com)"));
+            let (username, email) = longest_user(i);
+            commands.push(format!("insert {i} {username} {email}"));
+            expected.push(format!("({i}, {username}, {email})"));
         }
         commands.push(String::from_str(".exit").unwrap());
 
@@ -133,6 +367,33 @@ mod tests {
         cmd.assert().success().stdout(expected);
     }
 
+    #[test]
+    fn it_measures_text_length_in_characters() {
+        let username = "ユ".repeat(USERNAME_SIZE);
+        let long_username = "ユ".repeat(USERNAME_SIZE + 1);
+        let mut input = format!(
+            "insert 1 {} a@example.com\ninsert 2 {} b@example.com\n",
+            username, long_username
+        )
+        .into_bytes();
+        input.extend_from_slice(b"insert 3 caf\xe9 c@example.com\nselect\n.exit");
+
+        let db_path = create_db_path();
+        let mut cmd = Command::cargo_bin("rust-sqlite").expect("Failed to run command");
+        cmd.arg(&db_path).write_stdin(input);
+
+        let expected = [
+            "db > Executed.".to_string(),
+            "db > String is too long.".to_string(),
+            "db > Input is not valid UTF-8.".to_string(),
+            format!("db > (1, {}, a@example.com)", username),
+            "Executed.".to_string(),
+            "db > ".to_string(),
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
     #[test]
     fn it_prints_error_message_if_id_is_negative() {
         let mut cmd = run_commands(&["insert -1 user1 person1@example.com", "select", ".exit"]);
@@ -143,16 +404,1368 @@ mod tests {
     }
 
     #[test]
-    fn it_keeps_data_after_closing_connection() {
+    fn it_stores_rows_in_the_space_they_need() {
         let db_path = create_db_path();
 
-        let mut cmd =
-            run_commands_with_args(&["insert 1 user1 person1@example.com", ".exit"], &db_path);
-        let expected = ["db > Executed.", "db > "].join("\n");
+        let mut commands = Vec::new();
+        for i in 0..ROWS_PER_PAGE * 4 {
+            commands.push(format!("insert {i} user{i} person{i}@example.com"));
+        }
+        commands.push(".exit".to_string());
+        let mut cmd = run_commands_with_args(&commands, &db_path);
+        cmd.assert().success();
+
+        // The file header, the catalog and a single leaf for the users.
+        let file_size = std::fs::metadata(&db_path)
+            .expect("Failed to read db metadata")
+            .len();
+        assert_eq!(file_size, 3 * PAGE_SIZE as u64);
+    }
+
+    #[test]
+    fn it_prints_error_message_if_key_is_duplicate() {
+        let mut cmd = run_commands(&[
+            "insert 1 user1 person1@example.com",
+            "insert 1 user1 person1@example.com",
+            "insert 0 user0 person0@example.com",
+            "select",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Error: Duplicate key.",
+            "db > Executed.",
+            "db > (0, user0, person0@example.com)",
+            "(1, user1, person1@example.com)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+
         cmd.assert().success().stdout(expected);
+    }
 
-        let mut cmd = run_commands_with_args(&["select", ".exit"], &db_path);
-        let expected = ["db > (1, user1, person1@example.com)\nExecuted.", "db > "].join("\n");
+    #[test]
+    fn it_keys_rows_by_a_declared_primary_key() {
+        let mut cmd = run_commands(&[
+            "create table books (title text, isbn integer primary key)",
+            "insert into books dune 42",
+            "insert into books emma 42",
+            "insert into books (title) ulysses",
+            "insert into books (title) beloved",
+            "select * from books",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: Duplicate key.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (dune, 42)",
+            "(ulysses, 43)",
+            "(beloved, 44)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_keeps_a_text_primary_key_unique() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table codes (id integer, code text primary key, label text)",
+                "insert into codes 7 abc first",
+                "insert into codes 8 abc second",
+                "insert into codes 9 xyz third",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: UNIQUE constraint failed: codes.code = abc.",
+            "db > Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+
+        // The index behind the key is kept with the table.
+        let mut cmd = run_commands_with_args(
+            &[
+                "insert into codes 10 xyz fourth",
+                "update codes set code = 'abc' where id = 9",
+                "select * from codes",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Error: UNIQUE constraint failed: codes.code = xyz.",
+            "db > Error: UNIQUE constraint failed: codes.code = abc.",
+            "db > (7, abc, first)",
+            "(9, xyz, third)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_enforces_unique_constraints() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table members (id integer, email text unique, team text, seat integer, unique (team, seat))",
+                "insert into members 1 a@example.com red 1",
+                "insert into members 2 a@example.com red 2",
+                "insert into members 2 b@example.com red 1",
+                "insert into members 2 null red null",
+                "insert into members 3 null red null",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: UNIQUE constraint failed: members.email = a@example.com.",
+            "db > Error: UNIQUE constraint failed: members.team, members.seat = red, 1.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+
+        // The indexes are found again when the file is reopened.
+        let mut cmd = run_commands_with_args(
+            &[
+                "update members set email = 'a@example.com' where id = 2",
+                "update members set seat = 1 where id = 3",
+                "update members set email = 'c@example.com', seat = 2 where id = 3",
+                "update members set email = 'a@example.com' where id = 1",
+                "insert into members 4 c@example.com blue 1",
+                "select * from members",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Error: UNIQUE constraint failed: members.email = a@example.com.",
+            "db > Error: UNIQUE constraint failed: members.team, members.seat = red, 1.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: UNIQUE constraint failed: members.email = c@example.com.",
+            "db > (1, a@example.com, red, 1)",
+            "(2, NULL, red, NULL)",
+            "(3, c@example.com, red, 2)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_enforces_check_constraints() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table accounts (id integer, username text check (length(username) > 0), email text constraint email_format check (email LIKE '%@%'), balance integer, check (balance >= 0 or balance is null))",
+                "insert into accounts 1 alice alice@example.com 10",
+                "insert into accounts 2 '' bob@example.com 10",
+                "insert into accounts 3 carol carol.example.com 10",
+                "insert into accounts 4 dave dave@example.com -5",
+                "insert into accounts 5 erin null null",
+                "update accounts set balance = -1 where id = 1",
+                "update accounts set balance = 0 where id = 1",
+                "select * from accounts",
+                "create table bad (a integer check (b > 0))",
+                "create table bad (a integer check (a >))",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: CHECK constraint failed: length(username) > 0.",
+            "db > Error: CHECK constraint failed: email_format.",
+            "db > Error: CHECK constraint failed: balance >= 0 or balance is null.",
+            "db > Executed.",
+            "db > Error: CHECK constraint failed: balance >= 0 or balance is null.",
+            "db > Executed.",
+            "db > (1, alice, alice@example.com, 0)",
+            "(5, erin, NULL, NULL)",
+            "Executed.",
+            "db > Syntax error: Unknown column 'b'",
+            "db > Syntax error: Expected an expression but found ')' at offset 38",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_enforces_foreign_keys() {
+        let mut cmd = run_commands(&[
+            "create table orders (id integer primary key, user_id integer references users (id) on delete cascade, note text references notes)",
+            "create table orders (id integer primary key, user_id integer references users (username))",
+            "create table orders (id integer primary key, user_id integer references users on delete cascade)",
+            "create table tags (id integer primary key, user_id integer, foreign key (user_id) references users on delete set null)",
+            "create table invoices (id integer primary key, order_id integer references orders (id) on delete restrict)",
+            "insert 1 alice alice@example.com",
+            "insert 2 bob bob@example.com",
+            "insert into orders 10 1",
+            "insert into orders 11 3",
+            "insert into orders 12 2",
+            "insert into orders 13 null",
+            "update orders set user_id = 3 where id = 10",
+            "update users set id = 5 where id = 1",
+            "insert into tags 20 1",
+            "insert into invoices 30 12",
+            "delete from users where id = 2",
+            "delete from users where id = 1",
+            "select * from orders",
+            "select * from tags",
+            "select",
+            ".exit",
+        ]);
+        let expected = [
+            "db > Unknown table notes.",
+            "db > Syntax error: Foreign key orders.user_id must reference a primary key or unique column of users",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: FOREIGN KEY constraint failed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: FOREIGN KEY constraint failed.",
+            "db > Error: FOREIGN KEY constraint failed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: FOREIGN KEY constraint failed.",
+            "db > Executed.",
+            "db > (12, 2)",
+            "(13, NULL)",
+            "Executed.",
+            "db > (20, NULL)",
+            "Executed.",
+            "db > (2, bob, bob@example.com)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_checks_deferred_foreign_keys_when_the_statement_ends() {
+        let mut cmd = run_commands(&[
+            "create table nodes (id integer primary key, parent integer references nodes deferrable initially deferred)",
+            "create table items (id integer primary key, parent integer references items)",
+            "insert into nodes 1 null",
+            "insert into nodes 2 1",
+            "insert into nodes 3 9",
+            "delete from nodes where id = 1",
+            "insert into items 1 null",
+            "insert into items 2 1",
+            "delete from items",
+            "delete from nodes",
+            "select * from nodes",
+            "select * from items",
+            ".exit",
+        ]);
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: FOREIGN KEY constraint failed.",
+            "db > Error: FOREIGN KEY constraint failed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: FOREIGN KEY constraint failed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (1, NULL)",
+            "(2, 1)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_alters_tables() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table people (id integer primary key, name text unique, age integer check (age >= 0))",
+                "create table pets (id integer primary key, owner text references people (name))",
+                "insert into people 1 ann 30",
+                "alter table people add column city text default 'Paris'",
+                "alter table people add zip integer not null",
+                "alter table people add id2 integer primary key",
+                "insert into people 2 bob 40 Rome",
+                "select * from people",
+                "alter table people rename column age to years",
+                "alter table people rename name to full_name",
+                "update people set years = -1 where id = 1",
+                "alter table people drop column years",
+                "alter table people drop column full_name",
+                "alter table people rename to persons",
+                "alter table pets rename to persons",
+                "insert into pets 7 carl",
+                "insert into pets 7 ann",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Syntax error: Cannot add a NOT NULL column zip without a default",
+            "db > Syntax error: Cannot add a PRIMARY KEY column id2",
+            "db > Executed.",
+            "db > (1, ann, 30, Paris)",
+            "(2, bob, 40, Rome)",
+            "Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: CHECK constraint failed: years >= 0.",
+            "db > Executed.",
+            "db > Syntax error: Cannot drop column full_name",
+            "db > Executed.",
+            "db > Error: Table persons already exists.",
+            "db > Error: FOREIGN KEY constraint failed.",
+            "db > Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "select * from persons",
+                "select * from rust_sqlite_schema",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > (1, ann, Paris)",
+            "(2, bob, Rome)",
+            "Executed.",
+            "db > (table, users, users, 2, create table users (id integer, username text(32), email text(255)))",
+            "(table, persons, persons, 3, create table persons (id integer primary key, full_name text unique, city text default 'Paris'))",
+            "(index, rust_sqlite_autoindex_persons_1, persons, 4, NULL)",
+            "(table, pets, pets, 5, create table pets (id integer primary key, owner text references persons (full_name)))",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_reuses_the_pages_of_dropped_tables() {
+        let db_path = create_db_path();
+        let file_length = || std::fs::metadata(&db_path).expect("Missing file").len();
+        let fill = |table: &str| -> Vec<String> {
+            (1..=ROWS_PER_PAGE * 4)
+                .map(|i| {
+                    let (username, email) = longest_user(i);
+                    format!("insert into {} {} {} {}", table, i, username, email)
+                })
+                .collect()
+        };
+
+        let mut commands = vec![
+            "create table logs (id integer, username text(32), email text(255) unique)".to_string(),
+        ];
+        commands.extend(fill("logs"));
+        commands.push(".exit".to_string());
+        run_commands_with_args(&commands, &db_path)
+            .assert()
+            .success();
+        let full_length = file_length();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "drop index rust_sqlite_autoindex_logs_1",
+                "drop index missing",
+                "drop table rust_sqlite_schema",
+                "drop table logs",
+                "select * from logs",
+                "select * from rust_sqlite_schema",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Syntax error: Index rust_sqlite_autoindex_logs_1 belongs to a UNIQUE constraint of logs and cannot be dropped",
+            "db > Unknown index missing.",
+            "db > Table rust_sqlite_schema may not be modified.",
+            "db > Executed.",
+            "db > Unknown table logs.",
+            "db > (table, users, users, 2, create table users (id integer, username text(32), email text(255)))",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+
+        let mut commands = vec![
+            "create table archive (id integer, username text(32), email text(255) unique)"
+                .to_string(),
+        ];
+        commands.extend(fill("archive"));
+        commands.push(".exit".to_string());
+        run_commands_with_args(&commands, &db_path)
+            .assert()
+            .success();
+        assert_eq!(file_length(), full_length);
+    }
+
+    #[test]
+    fn it_assigns_rowids_to_rows_without_a_key() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "insert null alice alice@example.com",
+                "insert into users (username, email) bob bob@example.com",
+                "select",
+                "create table events (id integer primary key autoincrement, name text)",
+                "create table notes (id integer primary key, name text)",
+                "insert into events (name) a",
+                "insert into events (name) b",
+                "insert into notes (name) a",
+                "insert into notes (name) b",
+                "delete from events where id = 2",
+                "delete from notes where id = 2",
+                "insert into events (name) c",
+                "insert into notes (name) c",
+                "select * from events",
+                "select * from notes",
+                "drop table rust_sqlite_sequence",
+                "create table rust_sqlite_stat (a integer)",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > (1, alice, alice@example.com)",
+            "(2, bob, bob@example.com)",
+            "Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (1, a)",
+            "(3, c)",
+            "Executed.",
+            "db > (1, a)",
+            "(2, c)",
+            "Executed.",
+            "db > Table rust_sqlite_sequence may not be modified.",
+            "db > Syntax error: Table name rust_sqlite_stat is reserved for internal use",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+
+        // The sequence survives the largest key being deleted and the
+        // database being reopened.
+        let mut cmd = run_commands_with_args(
+            &[
+                "delete from events where id = 3",
+                "insert into events (name) d",
+                "select * from events",
+                "select * from rust_sqlite_sequence",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > (1, a)",
+            "(4, d)",
+            "Executed.",
+            "db > (events, 4)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_reports_the_last_inserted_rowid() {
+        let db_path = create_db_path();
+        let mut database = Database::db_open(&db_path).expect("Unable to open database");
+        assert_eq!(database.last_insert_rowid(), 0);
+
+        for (input, rowid) in [
+            (
+                "insert into users (username, email) alice alice@example.com",
+                1,
+            ),
+            ("insert 10 bob bob@example.com", 10),
+            ("insert null carol carol@example.com", 11),
+            ("insert 10 dave dave@example.com", 11),
+        ] {
+            // Failing inserts, like the duplicate key, leave it unchanged.
+            if let Ok(statement) = Statement::prepare(input, &database) {
+                let _ = statement.execute(&mut database);
+            }
+            assert_eq!(database.last_insert_rowid(), rowid, "after {}", input);
+        }
+        database.db_close().expect("Unable to close database");
+    }
+
+    #[test]
+    fn it_compares_text_with_collations() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table people (id integer primary key, name text collate nocase unique, nick text check (nick = 'bob' collate nocase))",
+                "insert into people 1 Alice Bob",
+                "insert into people 2 alice BOB",
+                "insert into people 3 carol bob",
+                "insert into people 4 Bob boB",
+                "insert into people 5 dave robert",
+                "create table pets (id integer primary key, name text collate klingon)",
+                "select * from people order by name desc",
+                "select * from people order by name collate binary",
+                "update people set nick = 'BOB' where name = 'CAROL'",
+                "create index people_nick on people (nick)",
+                "delete from people where name = 'ALICE'",
+                "select * from people",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: UNIQUE constraint failed: people.name = alice.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: CHECK constraint failed: nick = 'bob' collate nocase.",
+            "db > Unknown collation klingon.",
+            "db > (3, carol, bob)",
+            "(4, Bob, boB)",
+            "(1, Alice, Bob)",
+            "Executed.",
+            "db > (1, Alice, Bob)",
+            "(4, Bob, boB)",
+            "(3, carol, bob)",
+            "Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (3, carol, BOB)",
+            "(4, Bob, boB)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_registers_collations() {
+        let db_path = create_db_path();
+        let mut database = Database::db_open(&db_path).expect("Unable to open database");
+        let mut run = |input: &str| -> Result<Vec<String>, String> {
+            let statement = Statement::prepare(input, &database).map_err(|e| e.to_string())?;
+            let rows = statement
+                .execute(&mut database)
+                .map_err(|e| e.to_string())?;
+            Ok(rows.iter().map(|row| row.to_string()).collect())
+        };
+
+        let create = "create table files (id integer primary key, name text collate natural)";
+        assert_eq!(run(create), Err("Unknown collation natural.".to_string()));
+        // Numbers sort by value once padded to the same width.
+        Collation::register(Collation::new("natural", |text| format!("{:0>8}", text)));
+        assert_eq!(run(create), Ok(Vec::new()));
+        for id in [1, 2, 3] {
+            let name = ["10", "9", "100"][id - 1];
+            run(&format!("insert into files {} {}", id, name)).expect("Unable to insert");
+        }
+        assert_eq!(
+            run("select * from files order by name"),
+            Ok(vec![
+                "(2, 9)".to_string(),
+                "(1, 10)".to_string(),
+                "(3, 100)".to_string()
+            ])
+        );
+        database.db_close().expect("Unable to close database");
+    }
+
+    #[test]
+    fn it_tokenizes_sql_with_offsets() {
+        let sql =
+            "SELECT \"user name\", x'0A' -- note\nFROM t /* c */ WHERE id >= ?1 AND n = 'it''s'";
+        let tokens: Vec<(TokenKind, usize)> = tokenize(sql)
+            .expect("Unable to tokenize")
+            .into_iter()
+            .map(|token| (token.kind, token.offset))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Keyword("select"), 0),
+                (TokenKind::Identifier("user name".to_string()), 7),
+                (TokenKind::Symbol(","), 18),
+                (TokenKind::Blob(vec![10]), 20),
+                (TokenKind::Keyword("from"), 34),
+                (TokenKind::Identifier("t".to_string()), 39),
+                (TokenKind::Keyword("where"), 49),
+                (TokenKind::Identifier("id".to_string()), 55),
+                (TokenKind::Symbol(">="), 58),
+                (TokenKind::Parameter("?1".to_string()), 61),
+                (TokenKind::Keyword("and"), 64),
+                (TokenKind::Identifier("n".to_string()), 68),
+                (TokenKind::Symbol("="), 70),
+                (TokenKind::String("it's".to_string()), 72),
+            ]
+        );
+        assert_eq!(
+            tokenize("select 'open").map_err(|e| e.to_string()).err(),
+            Some(
+                "Syntax error: Expected a closing ' but found end of input at offset 12"
+                    .to_string()
+            )
+        );
+
+        let mut cmd = run_commands(&["selectXYZ", "SELECT", ".exit"]);
+        let expected = ["db > Unrecognized statement.", "db > Executed.", "db > "].join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_parses_statements_into_an_ast() {
+        let statement = parse("SELECT * FROM t ORDER BY a DESC;").unwrap();
+        assert_eq!(
+            statement,
+            ast::Statement::Select(ast::Select {
+                columns: vec![ast::ResultColumn::All],
+                from: Some(ast::TableRef::Table("t".to_string())),
+                filter: None,
+                order_by: Some(ast::OrderBy {
+                    expr: Expr::Column("a".to_string()),
+                    descending: true,
+                }),
+            })
+        );
+        assert_eq!(
+            parse("delete from t where id = 1").unwrap(),
+            ast::Statement::Delete(ast::Delete {
+                table: "t".to_string(),
+                filter: Some(Expr::parse("id = 1").unwrap()),
+            })
+        );
+
+        match parse("create table t (id integer,)") {
+            Err(PrepareError::SyntaxError {
+                expected,
+                found,
+                offset,
+            }) => {
+                assert_eq!(expected, "a column name");
+                assert_eq!(found.as_deref(), Some(")"));
+                assert_eq!(offset, 27);
+            }
+            other => panic!("Expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_creates_secondary_indexes() {
+        let db_path = create_db_path();
+
+        let mut commands: Vec<String> = (1..=ROWS_PER_PAGE * 4)
+            .map(|i| {
+                let (username, email) = longest_user(i);
+                format!("insert {} {} {}", i, username, email)
+            })
+            .collect();
+        commands.extend(
+            [
+                "insert 100 dup dup@example.com",
+                "insert 101 dup dup2@example.com",
+                "create unique index users_username on users (username)",
+                "create index users_username on users (username)",
+                "create index users_username on users (username)",
+                "create index users_email on users (nickname)",
+                "create unique index users_email on users (email)",
+                "insert 102 dup2 dup@example.com",
+                "update users set username = 'renamed' where username = 'dup'",
+                "delete from users where email = 'dup2@example.com'",
+                "select * from rust_sqlite_schema",
+                ".exit",
+            ]
+            .map(String::from),
+        );
+        let output = run_commands_with_args(&commands, &db_path)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let output = String::from_utf8_lossy(&output);
+        let expected = [
+            "db > Error: UNIQUE constraint failed: users.username = dup.",
+            "db > Executed.",
+            "db > Error: Index users_username already exists.",
+            "db > Syntax error: Table users has no column 'nickname'",
+            "db > Executed.",
+            "db > Error: UNIQUE constraint failed: users.email = dup@example.com.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (table, users, users, 2, create table users (id integer, username text(32), email text(255)))",
+            "(index, users_username, users, 7, create index users_username on users (username))",
+            "(index, users_email, users, 8, create unique index users_email on users (email))",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        assert!(output.ends_with(&expected), "{}", output);
+
+        // Lookups through the index read fewer pages than a full scan.
+        let mut cmd = run_commands_with_args(
+            &[
+                "alter table users rename column username to name",
+                "update users set email = 'a@example.com' where name = 'renamed'",
+                ".stats",
+                "update users set name = 'b' where id = 100",
+                ".stats",
+                "select * from rust_sqlite_schema",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let text = String::from_utf8_lossy(&output);
+        assert!(text.contains(
+            "(index, users_username, users, 7, create index users_username on users (name))"
+        ));
+        let lines: Vec<&str> = text.lines().filter(|l| l.contains("cache hits")).collect();
+        let hits = |line: &str| stat(line.as_bytes(), "cache hits").1;
+        assert!(hits(lines[0]) < hits(lines[1]), "{}", text);
+    }
+
+    #[test]
+    fn it_alters_tables_whose_definitions_hold_quotes_parentheses_and_commas() {
+        let mut cmd = run_commands(&[
+            "create table w (a text default '(', b integer)",
+            "alter table w rename column b to z",
+            "create table t (a text default 'x,y', b integer, c integer)",
+            "alter table t drop column c",
+            "create table q (\"first name\" text default 'b, (c)', b integer check (b <> 'b' and length(\"first name\") > 0), c integer, unique (\"first name\", b))",
+            "alter table q rename column b to \"order\"",
+            "alter table q rename column \"first name\" to given",
+            "alter table q drop column c",
+            "alter table q rename to \"my table\"",
+            "insert into \"my table\" ann 0",
+            "insert into \"my table\" '' 1",
+            "select * from rust_sqlite_schema",
+            ".exit",
+        ]);
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: CHECK constraint failed: \"order\" <> 'b' and length(given) > 0.",
+            "db > (table, users, users, 2, create table users (id integer, username text(32), email text(255)))",
+            "(table, w, w, 3, create table w (a text default '(', z integer))",
+            "(table, t, t, 4, create table t (a text default 'x,y', b integer))",
+            "(table, my table, my table, 5, create table \"my table\" (given text default 'b, (c)', \"order\" integer check (\"order\" <> 'b' and length(given) > 0), unique (given, \"order\")))",
+            "(index, rust_sqlite_autoindex_my table_1, my table, 6, NULL)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_keeps_data_after_closing_connection() {
+        let db_path = create_db_path();
+
+        let mut cmd =
+            run_commands_with_args(&["insert 1 user1 person1@example.com", ".exit"], &db_path);
+        let expected = ["db > Executed.", "db > "].join("\n");
+        cmd.assert().success().stdout(expected);
+
+        let mut cmd = run_commands_with_args(&["select", ".exit"], &db_path);
+        let expected = ["db > (1, user1, person1@example.com)\nExecuted.", "db > "].join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_reads_ahead_pages_on_sequential_scans() {
+        let db_path = create_db_path();
+        let rows = (READ_AHEAD_PAGES + 4) * ROWS_PER_PAGE;
+
+        let mut commands = Vec::new();
+        let mut expected = Vec::new();
+        for i in 1..=rows {
+            let (username, email) = longest_user(i);
+            commands.push(format!("insert {i} {username} {email}"));
+            expected.push(format!("({i}, {username}, {email})"));
+        }
+        commands.push(".exit".to_string());
+        run_commands_with_args(&commands, &db_path)
+            .assert()
+            .success();
+
+        expected[0] = format!("db > {}", expected[0]);
+        expected.extend(["Executed.".to_string(), "db > ".to_string()]);
+        let mut cmd = run_commands_with_args(&["select", ".exit"], &db_path);
+        cmd.assert().success().stdout(expected.join("\n"));
+
+        // Two misses in a row load the following pages in one read, so
+        // changing them on disk afterwards goes unnoticed, unlike a page
+        // past the read-ahead window.
+        let on_disk = std::fs::read(&db_path).expect("Failed to read db file");
+        let page = |num: usize| &on_disk[num * PAGE_SIZE..(num + 1) * PAGE_SIZE];
+        let mut pager = Pager::open(&db_path).expect("Failed to open pager");
+        pager.get_page(1);
+        pager.get_page(2);
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&db_path)
+            .expect("Failed to open db file");
+        for num in [5, 2 + READ_AHEAD_PAGES] {
+            file.seek(SeekFrom::Start((num * PAGE_SIZE) as u64))
+                .and_then(|_| file.write_all(&[0; PAGE_SIZE]))
+                .expect("Failed to overwrite page");
+        }
+
+        assert_eq!(pager.get_page(5)[..], *page(5));
+        assert_eq!(pager.get_page(2 + READ_AHEAD_PAGES)[..], [0; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn it_rejects_writes_in_readonly_mode() {
+        let db_path = create_db_path();
+
+        let mut cmd =
+            run_commands_with_args(&["insert 1 user1 person1@example.com", ".exit"], &db_path);
+        cmd.assert().success();
+
+        let mut cmd = run_commands_with_flags(
+            &["insert 2 user2 person2@example.com", "select", ".exit"],
+            &db_path,
+            &["--readonly"],
+        );
+        let expected = [
+            "db > Error: Database is read-only.",
+            "db > (1, user1, person1@example.com)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_does_not_modify_the_file_in_readonly_mode() {
+        let db_path = create_db_path();
+
+        let mut cmd =
+            run_commands_with_args(&["insert 1 user1 person1@example.com", ".exit"], &db_path);
+        cmd.assert().success();
+        let modified = |path: &Path| {
+            std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .expect("Failed to read db metadata")
+        };
+        let before = modified(&db_path);
+
+        let mut cmd = run_commands_with_flags(&["select", ".exit"], &db_path, &["--readonly"]);
+        cmd.assert().success();
+
+        assert_eq!(before, modified(&db_path));
+    }
+
+    // Reads the total and last statement columns of a `.stats` counter.
+    fn stat(output: &[u8], name: &str) -> (u64, u64) {
+        let output = String::from_utf8_lossy(output);
+        let line = output
+            .lines()
+            .map(|line| line.trim_start_matches("db > "))
+            .find(|line| line.starts_with(name))
+            .expect("Counter not found");
+        let values: Vec<u64> = line[name.len()..]
+            .split_whitespace()
+            .map(|value| value.parse().expect("Invalid counter"))
+            .collect();
+        (values[0], values[1])
+    }
+
+    #[test]
+    fn it_prints_io_counters_for_the_last_statement() {
+        let mut cmd = run_commands(&[
+            "insert 1 user1 person1@example.com",
+            "select",
+            ".stats",
+            ".exit",
+        ]);
+
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let (total_hits, select_hits) = stat(&output, "cache hits");
+        assert!(select_hits > 0);
+        assert!(total_hits > select_hits);
+        assert_eq!(stat(&output, "cache misses").1, 0);
+        assert_eq!(stat(&output, "pages written"), (0, 0));
+        // The cache holds every page, so nothing is evicted.
+        assert_eq!(stat(&output, "evictions"), (0, 0));
+    }
+
+    #[test]
+    fn it_reads_ahead_when_scanning_the_table() {
+        let mut commands = Vec::new();
+        for i in 0..4 * READ_AHEAD_PAGES * ROWS_PER_PAGE {
+            let (username, email) = longest_user(i);
+            commands.push(format!("insert {i} {username} {email}"));
+        }
+        commands.push(String::from(".exit"));
+
+        let db_path = create_db_path();
+        run_commands_with_args(&commands, &db_path)
+            .assert()
+            .success();
+
+        // Leaves are appended in key order, so the scan reads them as a
+        // sequential run and most of them come in through read-ahead.
+        let mut cmd = run_commands_with_args(&["select", ".stats", ".exit"], &db_path);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let (_, misses) = stat(&output, "cache misses");
+        let (_, pages_read) = stat(&output, "pages read");
+        assert!(pages_read >= 3 * READ_AHEAD_PAGES as u64);
+        assert!(misses <= pages_read.div_ceil(READ_AHEAD_PAGES as u64));
+    }
+
+    #[test]
+    fn it_reads_out_of_order_leaves_one_page_at_a_time() {
+        let mut commands = Vec::new();
+        for i in (0..4 * READ_AHEAD_PAGES * ROWS_PER_PAGE).rev() {
+            let (username, email) = longest_user(i);
+            commands.push(format!("insert {i} {username} {email}"));
+        }
+        commands.push(String::from(".exit"));
+
+        let db_path = create_db_path();
+        run_commands_with_args(&commands, &db_path)
+            .assert()
+            .success();
+
+        // Inserting keys in descending order appends every new leaf after
+        // the ones holding larger keys, so the scan walks the leaves
+        // backwards through the file and read-ahead never kicks in.
+        let mut cmd = run_commands_with_args(&["select", ".stats", ".exit"], &db_path);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let (_, misses) = stat(&output, "cache misses");
+        let (_, pages_read) = stat(&output, "pages read");
+        assert!(pages_read >= 3 * READ_AHEAD_PAGES as u64);
+        assert_eq!(misses, pages_read);
+    }
+
+    #[test]
+    fn it_creates_a_table_with_a_user_defined_schema() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table books (isbn integer, title text(64), pages integer)",
+                "insert into books 42 dune 412",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = ["db > Executed.", "db > Executed.", "db > "].join("\n");
+        cmd.assert().success().stdout(expected);
+
+        let mut cmd = run_commands_with_args(
+            &["select * from books", "insert into books 1 user1", ".exit"],
+            &db_path,
+        );
+        let expected = [
+            "db > (42, dune, 412)",
+            "Executed.",
+            "db > Syntax error: Expected 'insert into books <isbn> <title> <pages>'",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_stores_values_of_every_type() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table readings (id integer, value real, note text(16), raw blob(4), misc)",
+                "insert into readings 1 2 '007' x'CAFE' null",
+                "insert into readings 2 1.5 'it''s' 3 'text'",
+                "insert into readings 3 abc 42 null 9223372036854775807",
+                "insert into readings 4 null null null -0.25",
+                "select * from readings",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > (1, 2.0, 007, x'CAFE', NULL)",
+            "(2, 1.5, it's, 3, text)",
+            "(3, abc, 42, NULL, 9223372036854775807)",
+            "(4, NULL, NULL, NULL, -0.25)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_converts_values_to_the_column_affinity() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table t (i int, n numeric, r double, s varchar(8))",
+                "insert into t 3.0 '12' 7 4.5",
+                "insert into t 3.5 1e3 '2.5' -12",
+                "insert into t 1 2 3 toolongvalue",
+                "select * from t",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > String is too long.",
+            "db > (3, 12, 7.0, 4.5)",
+            "(3.5, 1000, 2.5, -12)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_fills_omitted_columns_with_defaults() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table tasks (id integer not null, title text not null, done integer default 0, note text)",
+                "insert into tasks (id, title) 1 write",
+                "insert into tasks (title, id, note) review 2 'asap'",
+                "insert into tasks (id) 3",
+                "insert into tasks 4 null 1 none",
+                "insert into tasks (id, title) 5",
+                "insert into tasks (id, owner) 6 me",
+                "select * from tasks",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: NOT NULL constraint failed: tasks.title.",
+            "db > Error: NOT NULL constraint failed: tasks.title.",
+            "db > Syntax error: Expected 'insert into tasks (id, title) <id> <title>'",
+            "db > Syntax error: Table tasks has no column 'owner'",
+            "db > (1, write, 0, NULL)",
+            "(2, review, 0, asap)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_computes_dates_and_times() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table events (id integer primary key, day text check (date(day) is day), due text default (date('2024-01-31', '+1 month')), at text default (datetime(1700000000, 'unixepoch', 'start of month', '-1 day')), stamp text default (strftime('%j %H:%M:%f %w %s', '2024-03-01T10:20:30.5+02:00')))",
+                "insert into events (id, day) 1 2024-10-18",
+                "insert into events (id, day) 2 2024-02-30",
+                "insert into events (id, day) 3 '18/10/2024'",
+                "alter table events add column seen integer default (unixepoch())",
+                "alter table events add column seen text default current_timestamp",
+                "select * from events",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: CHECK constraint failed: date(day) is day.",
+            "db > Error: CHECK constraint failed: date(day) is day.",
+            "db > Syntax error: Cannot add a column seen with non-constant default",
+            "db > Syntax error: Cannot add a column seen with non-constant default",
+            "db > (1, 2024-10-18, 2024-03-02, 2023-10-31 00:00:00, 061 08:20:30.500 5 1709281230)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_returns_null_for_dates_out_of_range() {
+        let mut cmd = run_commands(&[
+            "select date('now', '+1e300 days'), date('now', '-1e400 hours')",
+            "select date('now', '+1e18 months'), date('2024-01-31', '-1e18 years')",
+            "select date('nan'), date('inf'), date('2024-01-31', 'nan days')",
+            "select date('2460000.5'), date('2024-01-31', '+1 month')",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > (NULL, NULL)",
+            "Executed.",
+            "db > (NULL, NULL)",
+            "Executed.",
+            "db > (NULL, NULL, NULL)",
+            "Executed.",
+            "db > (2023-02-25, 2024-03-02)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_queries_json_values() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                r#"create table docs (id integer primary key, doc json check (json_array_length(doc, '$.tags') > 0), name text default (json_extract('{"name":"x"}', '$.name')))"#,
+                r#"insert into docs (id, doc) 1 {"tags":["b","a"],"n":2}"#,
+                r#"insert into docs (id, doc) 2 {"tags":[],"n":1}"#,
+                r#"insert into docs (id, doc) 3 {"tags":["c"]"#,
+                r#"insert into docs (id, doc) 4 {"tags":["c"],"n":1}"#,
+                "select * from docs order by doc->'$.n'",
+                r#"select * from docs order by json_set(doc, '$.tags[0]', json('"z"'))->>'tags' desc"#,
+                r#"select * from json_each('{"a":[1,2.5],"b":"c"}', '$.a') order by value desc"#,
+                "select * from json_each(doc)",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: CHECK constraint failed: json_array_length(doc, '$.tags') > 0.",
+            "db > Error: JSON constraint failed: docs.doc.",
+            "db > Executed.",
+            r#"db > (4, {"tags":["c"],"n":1}, x)"#,
+            r#"(1, {"tags":["b","a"],"n":2}, x)"#,
+            "Executed.",
+            r#"db > (4, {"tags":["c"],"n":1}, x)"#,
+            r#"(1, {"tags":["b","a"],"n":2}, x)"#,
+            "Executed.",
+            "db > (1, 2.5, real, 2.5, $.a[1], $.a)",
+            "(0, 1, integer, 1, $.a[0], $.a)",
+            "Executed.",
+            "db > Syntax error: Unknown column 'doc' in json_each",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_keeps_tables_apart_in_the_same_file() {
+        let db_path = create_db_path();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "create table books (isbn integer, title text(64))",
+                "insert 1 user1 person1@example.com",
+                "insert into books 42 dune",
+                ".exit",
+            ],
+            &db_path,
+        );
+        cmd.assert().success();
+
+        let mut cmd = run_commands_with_args(
+            &[
+                "select",
+                "select * from books",
+                "select * from rust_sqlite_schema",
+                ".exit",
+            ],
+            &db_path,
+        );
+        let expected = [
+            "db > (1, user1, person1@example.com)",
+            "Executed.",
+            "db > (42, dune)",
+            "Executed.",
+            "db > (table, users, users, 2, create table users (id integer, username text(32), email text(255)))",
+            "(table, books, books, 3, create table books (isbn integer, title text(64)))",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_prints_error_message_if_table_already_exists() {
+        let mut cmd = run_commands(&[
+            "create table users (id integer, name text)",
+            "select * from books",
+            "insert into rust_sqlite_schema table t t 1 x",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Error: Table users already exists.",
+            "db > Unknown table books.",
+            "db > Table rust_sqlite_schema may not be modified.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_only_keys_the_users_table_by_an_undeclared_id() {
+        let mut cmd = run_commands(&[
+            "create table t (id integer, name text)",
+            "insert into t values (5, 'a'), (5, 'b')",
+            "select * from t",
+            "insert 5 user5 person5@example.com",
+            "insert 5 user5 person5@example.com",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > (5, a)",
+            "(5, b)",
+            "Executed.",
+            "db > Executed.",
+            "db > Error: Duplicate key.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_accepts_negative_keys_outside_the_users_table() {
+        let mut cmd = run_commands(&[
+            "create table t (id integer primary key, name text(3))",
+            "insert into t values (-1, 'a'), (2, 'b')",
+            "update t set id = -2 where id = 2",
+            "update t set name = 'long' where id = -1",
+            "select * from t",
+            "insert -1 user1 person1@example.com",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > Error: String is too long.",
+            "db > (-2, b)",
+            "(-1, a)",
+            "Executed.",
+            "db > ID must be positive.",
+            "db > ",
+        ]
+        .join("\n");
+        cmd.assert().success().stdout(expected);
+    }
+
+    #[test]
+    fn it_accepts_words_the_grammar_does_not_use_as_names() {
+        let mut cmd = run_commands(&[
+            "create table transaction (limit integer, all text, distinct text)",
+            "insert into transaction (limit, all, distinct) values (1, 'a', 'b')",
+            "select all, limit from transaction where distinct = 'b'",
+            ".exit",
+        ]);
+
+        let expected = [
+            "db > Executed.",
+            "db > Executed.",
+            "db > (a, 1)",
+            "Executed.",
+            "db > ",
+        ]
+        .join("\n");
         cmd.assert().success().stdout(expected);
     }
 }